@@ -0,0 +1,378 @@
+use std::sync::Arc;
+
+use itertools::Itertools;
+use num_bigint::BigUint;
+use serde::Serialize;
+
+use crate::{
+    factorize::Unfactored,
+    registers::{split_list, RegisterParseError, Registers},
+    simulator::{SimulationError, Simulator},
+};
+
+/// The most inputs a single batch may be given, so that a mistyped range cannot exhaust memory
+pub const MAX_INPUTS: usize = 100_000;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum BatchParseError {
+    #[error("Could not parse the range {0:?}, expected a..b or p^a..p^b")]
+    BadRange(String),
+    #[error("A batch may have at most {MAX_INPUTS} inputs")]
+    TooManyInputs,
+    #[error(transparent)]
+    Registers(#[from] RegisterParseError),
+}
+
+/// Splits `p^a` into its base and exponent
+fn power(s: &str) -> Option<(&str, u64)> {
+    let (base, exp) = s.split_once('^')?;
+    Some((base.trim(), exp.trim().parse().ok()?))
+}
+
+fn parse_range(left: &str, right: &str) -> Result<Vec<Unfactored>, BatchParseError> {
+    let bad_range = || BatchParseError::BadRange(format!("{left}..{right}"));
+    // both ends are included, so an empty range is one whose end comes before its start
+    let too_long = |start: u64, end: u64| {
+        let len = end
+            .checked_sub(start)
+            .map_or(0, |diff| diff.saturating_add(1));
+        if len > MAX_INPUTS as u64 {
+            Err(BatchParseError::TooManyInputs)
+        } else {
+            Ok(())
+        }
+    };
+
+    match (power(left), power(right)) {
+        (Some((base, start)), Some((right_base, end))) if base == right_base => {
+            too_long(start, end)?;
+            let base: Unfactored = base.parse()?;
+            (start..=end)
+                .map(|exp| base.pow(exp).ok_or_else(bad_range))
+                .collect()
+        }
+        (None, None) => {
+            let start: u64 = left.parse().map_err(|_| bad_range())?;
+            let end: u64 = right.parse().map_err(|_| bad_range())?;
+            too_long(start, end)?;
+            (start..=end)
+                .map(|n| Unfactored::try_from(BigUint::from(n)).map_err(BatchParseError::from))
+                .try_collect()
+        }
+        _ => Err(bad_range()),
+    }
+}
+
+/// Parses a comma-separated list of inputs, each of which is either a single value, a range of
+/// numbers `a..b`, or a range of powers `p^a..p^b`. Both ends of a range are included. Numbers
+/// are left to be factored in the background.
+pub fn parse_inputs(text: &str) -> Result<Vec<Unfactored>, BatchParseError> {
+    let mut inputs = Vec::new();
+    for item in split_list(text).map(str::trim).filter(|s| !s.is_empty()) {
+        match item.split_once("..") {
+            Some((left, right)) => inputs.extend(parse_range(left.trim(), right.trim())?),
+            None => inputs.push(item.parse()?),
+        }
+        if inputs.len() > MAX_INPUTS {
+            return Err(BatchParseError::TooManyInputs);
+        }
+    }
+    Ok(inputs)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Outcome {
+    Halted,
+    Errored(SimulationError),
+    /// The program was still running when the tick limit was reached
+    TimedOut,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct BatchResult {
+    pub input: Registers,
+    pub outcome: Outcome,
+    pub output: Option<Registers>,
+    pub output_stream: Vec<Registers>,
+    pub steps: usize,
+}
+
+/// One line of an exported results table
+#[derive(Serialize)]
+struct BatchRow {
+    input: String,
+    status: &'static str,
+    output: String,
+    output_stream: String,
+    steps: usize,
+    error: String,
+}
+
+impl From<&BatchResult> for BatchRow {
+    fn from(result: &BatchResult) -> Self {
+        let (status, error) = match result.outcome {
+            Outcome::Halted => ("halted", String::new()),
+            Outcome::Errored(ref e) => ("error", e.to_string()),
+            Outcome::TimedOut => ("timeout", String::new()),
+        };
+        Self {
+            input: result.input.to_string(),
+            status,
+            output: result
+                .output
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            output_stream: result.output_stream.iter().join(" "),
+            steps: result.steps,
+            error,
+        }
+    }
+}
+
+/// Everything needed to run one input of a batch, shared between the workers
+struct BatchJob {
+    program: Simulator,
+    /// Each input, along with the input stream it is run with
+    inputs: Vec<(Registers, Vec<Registers>)>,
+    max_ticks: usize,
+}
+
+impl BatchJob {
+    fn run(&self, ix: usize) -> BatchResult {
+        let (ref input, ref stream) = self.inputs[ix];
+        let mut sim = self.program.program_copy();
+        let outcome = match sim
+            .start(input.clone(), stream.clone())
+            .and_then(|()| sim.run(self.max_ticks))
+        {
+            Ok(true) => Outcome::Halted,
+            Ok(false) => Outcome::TimedOut,
+            Err(e) => Outcome::Errored(e),
+        };
+        BatchResult {
+            input: input.clone(),
+            outcome,
+            output: sim.output().cloned(),
+            output_stream: sim.output_stream().to_vec(),
+            steps: sim.ticks(),
+        }
+    }
+}
+
+/// Runs one program on many inputs, independently of one another. Native builds hand the inputs
+/// out to worker threads as soon as the batch starts; web builds have no threads, so the inputs
+/// are run one after another whenever the batch is polled.
+pub struct BatchRun {
+    #[cfg(target_arch = "wasm32")]
+    job: Arc<BatchJob>,
+    results: Vec<Option<BatchResult>>,
+    finished: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: std::sync::mpsc::Receiver<(usize, BatchResult)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cancelled: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl BatchRun {
+    /// Starts running the program on each input, paired with the input stream to run it with
+    pub fn start(
+        program: &Simulator,
+        inputs: Vec<(Registers, Vec<Registers>)>,
+        max_ticks: usize,
+    ) -> Self {
+        let results = vec![None; inputs.len()];
+        let job = Arc::new(BatchJob {
+            program: program.program_copy(),
+            inputs,
+            max_ticks,
+        });
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let next = Arc::new(AtomicUsize::new(0));
+            let workers = std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+                .min(job.inputs.len());
+            for _ in 0..workers {
+                let (job, sender, cancelled, next) =
+                    (job.clone(), sender.clone(), cancelled.clone(), next.clone());
+                std::thread::spawn(move || {
+                    while !cancelled.load(Ordering::Relaxed) {
+                        let ix = next.fetch_add(1, Ordering::Relaxed);
+                        if ix >= job.inputs.len() || sender.send((ix, job.run(ix))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            Self {
+                results,
+                finished: 0,
+                receiver,
+                cancelled,
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        Self {
+            job,
+            results,
+            finished: 0,
+        }
+    }
+
+    fn insert(&mut self, ix: usize, result: BatchResult) {
+        self.results[ix] = Some(result);
+        self.finished += 1;
+    }
+
+    /// Collects the results which are ready. On web builds, runs inputs in order for as long as
+    /// `keep_going` allows, always running at least one.
+    pub fn poll(
+        &mut self,
+        #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))] keep_going: impl Fn() -> bool,
+    ) {
+        #[cfg(not(target_arch = "wasm32"))]
+        while let Ok((ix, result)) = self.receiver.try_recv() {
+            self.insert(ix, result);
+        }
+        #[cfg(target_arch = "wasm32")]
+        while !self.is_finished() {
+            let ix = self.finished;
+            self.insert(ix, self.job.run(ix));
+            if !keep_going() {
+                break;
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished == self.results.len()
+    }
+
+    /// How many inputs have been run, out of how many there are
+    pub fn progress(&self) -> (usize, usize) {
+        (self.finished, self.results.len())
+    }
+
+    /// The result for the input at `ix`, if it is ready
+    pub fn result(&self, ix: usize) -> Option<&BatchResult> {
+        self.results.get(ix)?.as_ref()
+    }
+
+    /// The results which are ready, in the order of their inputs
+    pub fn results(&self) -> impl Iterator<Item = &BatchResult> {
+        self.results.iter().flatten()
+    }
+
+    /// Exports the results which are ready as a CSV table
+    pub fn export_csv(&self) -> Result<Vec<u8>, csv::Error> {
+        let mut buf_out = Vec::new();
+        let mut writer = csv::Writer::from_writer(&mut buf_out);
+        for result in self.results() {
+            writer.serialize(BatchRow::from(result))?;
+        }
+        writer.flush()?;
+        drop(writer);
+        Ok(buf_out)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for BatchRun {
+    fn drop(&mut self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{factorize::Factoring, simulator::test_programs::transfer};
+
+    fn factored(text: &str) -> Vec<Registers> {
+        Factoring::start(parse_inputs(text).unwrap())
+            .wait()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_ranges() {
+        let inputs = factored("5, 2^0..2^3, 10..12");
+        assert_eq!(
+            inputs.iter().map(ToString::to_string).collect_vec(),
+            vec!["5", "1", "2", "4", "8", "10", "11", "12"]
+        );
+        assert_eq!(
+            factored("6^2..6^2"),
+            vec![Registers::from(vec![(2, 2), (3, 2)])]
+        );
+        assert_eq!(
+            factored("{2:3, 3:1}, 2^2 * 5"),
+            vec![
+                Registers::from(vec![(2, 3), (3, 1)]),
+                Registers::from(vec![(2, 2), (5, 1)])
+            ]
+        );
+        assert_eq!(parse_inputs(""), Ok(vec![]));
+        assert_eq!(
+            parse_inputs("2^1..3^2"),
+            Err(BatchParseError::BadRange("2^1..3^2".to_string()))
+        );
+        assert_eq!(
+            parse_inputs("1..1000000"),
+            Err(BatchParseError::TooManyInputs)
+        );
+        assert_eq!(parse_inputs("1..100000").unwrap().len(), MAX_INPUTS);
+        assert_eq!(
+            parse_inputs("2^0..2^100000"),
+            Err(BatchParseError::TooManyInputs)
+        );
+        assert_eq!(parse_inputs("5..4"), Ok(vec![]));
+    }
+
+    #[test]
+    fn run_batch() {
+        let inputs = factored("2^0..2^40");
+        let with_streams = inputs.iter().map(|input| (input.clone(), vec![])).collect();
+        let mut run = BatchRun::start(&transfer(), with_streams, 1000);
+        while !run.is_finished() {
+            run.poll(|| true);
+        }
+        let results = run.results().collect_vec();
+        assert_eq!(results.len(), inputs.len());
+        for (exp, result) in results.iter().enumerate() {
+            assert_eq!(result.input, inputs[exp]);
+            assert_eq!(result.outcome, Outcome::Halted);
+            assert_eq!(
+                result.output,
+                Some(Registers::from(vec![(3, 1)]).pow(exp as u64))
+            );
+        }
+
+        let inputs = factored("4, 8");
+        let mut run = BatchRun::start(
+            &transfer(),
+            inputs.into_iter().map(|input| (input, vec![])).collect(),
+            3,
+        );
+        while !run.is_finished() {
+            run.poll(|| true);
+        }
+        let csv = String::from_utf8(run.export_csv().unwrap()).unwrap();
+        assert_eq!(
+            csv.lines().collect_vec(),
+            vec![
+                "input,status,output,output_stream,steps,error",
+                "4,timeout,,,3,",
+                "8,timeout,,,3,",
+            ]
+        );
+    }
+}
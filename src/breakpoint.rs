@@ -0,0 +1,268 @@
+use std::{fmt::Display, str::FromStr};
+
+use glam::{ivec2, IVec2};
+
+use crate::{
+    l3x::L3XCommand,
+    registers::{Exponent, RegisterParseError, Registers},
+    simulator::Simulator,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Less,
+    LessEq,
+    Eq,
+    NotEq,
+    GreaterEq,
+    Greater,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        // two-character operators come first so that they are not mistaken for "<" or ">"
+        ("<=", Comparison::LessEq),
+        (">=", Comparison::GreaterEq),
+        ("==", Comparison::Eq),
+        ("!=", Comparison::NotEq),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn compare<T: Ord>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessEq => left <= right,
+            Comparison::Eq => left == right,
+            Comparison::NotEq => left != right,
+            Comparison::GreaterEq => left >= right,
+            Comparison::Greater => left > right,
+        }
+    }
+
+    /// Splits an expression of the form `left OP right` around its operator
+    fn split(s: &str) -> Option<(&str, Comparison, &str)> {
+        Self::ALL.iter().find_map(|&(op, comparison)| {
+            s.split_once(op)
+                .map(|(left, right)| (left.trim(), comparison, right.trim()))
+        })
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (op, _) = Self::ALL.iter().find(|(_, c)| c == self).unwrap();
+        write!(f, "{op}")
+    }
+}
+
+/// Something to check about the simulation after every step. The conditions on values hold when
+/// any traveler satisfies them, and only look at the factorization of each value, so they stay
+/// cheap no matter how large the values get.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Condition {
+    /// `exp(p) OP n`: the power of the prime `p` in a traveler's value
+    Exponent {
+        prime: u64,
+        comparison: Comparison,
+        value: u32,
+    },
+    /// `divisible(n)`: a traveler's value is a multiple of `n`
+    DivisibleBy(Registers),
+    /// `value == n`: a traveler's value is exactly `n`
+    Equals(Registers),
+    /// `travelers OP n`: the number of travelers currently on the grid
+    Travelers {
+        comparison: Comparison,
+        value: usize,
+    },
+    /// `empty(x,y)`: the queue at the given location has no numbers in it
+    QueueEmpty(IVec2),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ConditionParseError {
+    #[error("Expected one of exp(p) > n, divisible(n), value == n, travelers > n, or empty(x,y)")]
+    Unrecognized,
+    #[error("{0} is not prime")]
+    NotPrime(u64),
+    #[error("Could not parse the number {0:?}")]
+    BadNumber(String),
+    #[error(transparent)]
+    Registers(#[from] RegisterParseError),
+}
+
+fn number<T: FromStr>(s: &str) -> Result<T, ConditionParseError> {
+    s.trim()
+        .parse()
+        .map_err(|_| ConditionParseError::BadNumber(s.trim().to_string()))
+}
+
+/// Takes the argument out of a string of the form `name(argument)`
+fn call<'a>(s: &'a str, name: &str) -> Option<&'a str> {
+    s.strip_prefix(name)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+impl FromStr for Condition {
+    type Err = ConditionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(arg) = call(s, "divisible") {
+            return Ok(Condition::DivisibleBy(arg.trim().parse()?));
+        }
+        if let Some(arg) = call(s, "empty") {
+            let (x, y) = arg
+                .split_once(',')
+                .ok_or(ConditionParseError::Unrecognized)?;
+            return Ok(Condition::QueueEmpty(ivec2(number(x)?, number(y)?)));
+        }
+
+        let (left, comparison, right) =
+            Comparison::split(s).ok_or(ConditionParseError::Unrecognized)?;
+        match left {
+            "travelers" => Ok(Condition::Travelers {
+                comparison,
+                value: number(right)?,
+            }),
+            "value" if comparison == Comparison::Eq => Ok(Condition::Equals(right.parse()?)),
+            _ => {
+                let prime = number(call(left, "exp").ok_or(ConditionParseError::Unrecognized)?)?;
+                if !num_prime::nt_funcs::is_prime64(prime) {
+                    return Err(ConditionParseError::NotPrime(prime));
+                }
+                Ok(Condition::Exponent {
+                    prime,
+                    comparison,
+                    value: number(right)?,
+                })
+            }
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Exponent {
+                prime,
+                comparison,
+                value,
+            } => write!(f, "exp({prime}) {comparison} {value}"),
+            Condition::DivisibleBy(n) => write!(f, "divisible({n})"),
+            Condition::Equals(n) => write!(f, "value == {n}"),
+            Condition::Travelers { comparison, value } => {
+                write!(f, "travelers {comparison} {value}")
+            }
+            Condition::QueueEmpty(IVec2 { x, y }) => write!(f, "empty({x},{y})"),
+        }
+    }
+}
+
+impl Condition {
+    fn holds_for_value(&self, value: &Registers) -> bool {
+        match self {
+            Condition::Exponent {
+                prime,
+                comparison,
+                value: pow,
+            } => comparison.compare(value.exponent(*prime), Exponent::from(*pow)),
+            Condition::DivisibleBy(divisor) => value.try_div(divisor).is_some(),
+            Condition::Equals(n) => value == n,
+            Condition::Travelers { .. } | Condition::QueueEmpty(_) => false,
+        }
+    }
+
+    pub fn holds(&self, sim: &Simulator) -> bool {
+        match self {
+            Condition::Travelers { comparison, value } => {
+                comparison.compare(sim.travelers().len(), *value)
+            }
+            Condition::QueueEmpty(location) => {
+                sim.instructions
+                    .get(location)
+                    .map(|l3x| l3x.command == L3XCommand::Queue)
+                    .unwrap_or(false)
+                    && sim
+                        .queues()
+                        .get(location)
+                        .map(|queue| queue.is_empty())
+                        .unwrap_or(true)
+            }
+            _ => sim
+                .travelers()
+                .iter()
+                .any(|traveler| self.holds_for_value(&traveler.value)),
+        }
+    }
+}
+
+pub struct Breakpoint {
+    pub condition: Condition,
+    pub enabled: bool,
+}
+
+impl From<Condition> for Breakpoint {
+    fn from(condition: Condition) -> Self {
+        Self {
+            condition,
+            enabled: true,
+        }
+    }
+}
+
+impl Breakpoint {
+    pub fn is_hit(&self, sim: &Simulator) -> bool {
+        self.enabled && self.condition.holds(sim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_conditions() {
+        for text in [
+            "exp(3) > 10",
+            "divisible(35)",
+            "value == 1",
+            "travelers >= 5",
+            "empty(4,2)",
+        ] {
+            let condition: Condition = text.parse().unwrap();
+            assert_eq!(condition.to_string(), text);
+        }
+        assert_eq!(
+            "exp( 3 )<=2".parse(),
+            Ok(Condition::Exponent {
+                prime: 3,
+                comparison: Comparison::LessEq,
+                value: 2
+            })
+        );
+        assert_eq!(
+            "exp(4) > 1".parse::<Condition>(),
+            Err(ConditionParseError::NotPrime(4))
+        );
+        assert_eq!(
+            "value".parse::<Condition>(),
+            Err(ConditionParseError::Unrecognized)
+        );
+    }
+
+    #[test]
+    fn value_conditions() {
+        let value = Registers::from(vec![(3, 11), (5, 1), (7, 1)]);
+        let check = |text: &str| text.parse::<Condition>().unwrap().holds_for_value(&value);
+        assert!(check("exp(3) > 10"));
+        assert!(!check("exp(3) > 11"));
+        assert!(check("exp(2) == 0"));
+        assert!(check("divisible(35)"));
+        assert!(!check("divisible(4)"));
+        assert!(!check("value == 1"));
+    }
+}
@@ -0,0 +1,260 @@
+use std::{path::Path, process::ExitCode};
+
+use glam::UVec2;
+use itertools::Itertools;
+
+use crate::{
+    matrix::{read_program, MatrixMode},
+    registers::{split_list, Registers},
+    ruleset::Ruleset,
+    simulator::{QueueSemantics, Simulator},
+    trace::TraceFormat,
+};
+
+const USAGE: &str = "\
+Usage: l3x-ide run <FILE> [OPTIONS]
+
+Runs an .l3/.l3x/.csv program without opening a window. Without the run or help command, the IDE
+is opened.
+Values may be written as numbers, products of powers (2^10*3^5) or maps of powers ({2:10,3:5}).
+
+Options:
+  --input <N>        single input to the program (default: 1)
+  --stream <N,N,..>  input stream, only used in L3X mode (default: empty)
+  --mode <l3|l3x>    overrides the mode guessed from the file extension
+  --max-steps <N>    gives up after this many steps (default: 1000000)
+  --competition      enforces the limits from the CMIMC problem statement
+  --strict-queues    follows the spec for queues instead of letting numbers share them
+  --trace <FILE>     records every tick into FILE (CSV if it ends in .csv, else JSON Lines)
+
+Exit codes:
+  0  the program halted normally
+  1  the simulation raised an error
+  2  the arguments or the program file were invalid
+  3  the program did not halt within the step limit";
+
+const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum Exit {
+    Halted = 0,
+    SimulationError = 1,
+    BadInput = 2,
+    StepLimit = 3,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(value: Exit) -> Self {
+        ExitCode::from(value as u8)
+    }
+}
+
+struct RunArgs {
+    file: String,
+    input: Registers,
+    stream: Vec<Registers>,
+    mode: Option<MatrixMode>,
+    max_steps: usize,
+    ruleset: Ruleset,
+    queue_semantics: QueueSemantics,
+    trace: Option<String>,
+}
+
+fn parse_registers(arg: &str) -> Result<Registers, String> {
+    arg.trim()
+        .parse()
+        .map_err(|e| format!("could not parse value {arg:?}: {e}"))
+}
+
+fn parse_run_args(mut args: impl Iterator<Item = String>) -> Result<RunArgs, String> {
+    let mut file = None;
+    let mut input = Registers::ONE;
+    let mut stream = vec![];
+    let mut mode = None;
+    let mut max_steps = DEFAULT_MAX_STEPS;
+    let mut ruleset = Ruleset::Relaxed;
+    let mut queue_semantics = QueueSemantics::Relaxed;
+    let mut trace = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--input" => input = parse_registers(&value()?)?,
+            "--stream" => {
+                stream = split_list(&value()?)
+                    .filter(|s| !s.trim().is_empty())
+                    .map(parse_registers)
+                    .try_collect()?
+            }
+            "--mode" => {
+                mode = Some(
+                    MatrixMode::from_extension(&value()?.to_lowercase())
+                        .ok_or("mode must be either l3 or l3x")?,
+                )
+            }
+            "--max-steps" => {
+                max_steps = value()?
+                    .parse()
+                    .map_err(|e| format!("bad step limit: {e}"))?
+            }
+            "--competition" => ruleset = Ruleset::Competition,
+            "--strict-queues" => queue_semantics = QueueSemantics::Strict,
+            "--trace" => trace = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+            _ if file.is_none() => file = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+
+    Ok(RunArgs {
+        file: file.ok_or("no program file given")?,
+        input,
+        stream,
+        mode,
+        max_steps,
+        ruleset,
+        queue_semantics,
+        trace,
+    })
+}
+
+fn load_program(path: &str, mode: Option<MatrixMode>) -> Result<Simulator, String> {
+    let data = std::fs::read(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let mode = mode
+        .or_else(|| {
+            Path::new(path)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(MatrixMode::from_extension)
+        })
+        .unwrap_or_default();
+
+    let program = read_program(&data, mode).map_err(|e| format!("could not read csv: {e}"))?;
+    if program.dims == UVec2::ZERO {
+        return Err(format!("{path} is empty"));
+    }
+    if !program.issues.is_empty() {
+        return Err(format!(
+            "bad cells in {path}:\n  {}",
+            program.issues.iter().join("\n  ")
+        ));
+    }
+    Ok(Simulator::new(mode, program.instructions, program.dims))
+}
+
+fn write_trace(sim: &Simulator, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let format = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(TraceFormat::from_extension)
+        .unwrap_or(TraceFormat::JsonLines);
+    std::fs::write(path, sim.trace().export(format)?)?;
+    Ok(())
+}
+
+fn run(args: RunArgs) -> Exit {
+    let mut sim = match load_program(&args.file, args.mode) {
+        Ok(sim) => sim,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return Exit::BadInput;
+        }
+    };
+
+    sim.ruleset = args.ruleset;
+    sim.queue_semantics = args.queue_semantics;
+    sim.tracing = args.trace.is_some();
+    let violations = sim.rule_violations();
+    if !violations.is_empty() {
+        violations.iter().for_each(|v| eprintln!("error: {v}"));
+        return Exit::BadInput;
+    }
+    if let Err(e) = sim.start(args.input, args.stream) {
+        eprintln!("error: {e}");
+        return Exit::BadInput;
+    }
+
+    let exit = match sim.run(args.max_steps) {
+        Ok(true) => Exit::Halted,
+        Ok(false) => {
+            eprintln!(
+                "error: program did not halt within {} steps",
+                args.max_steps
+            );
+            Exit::StepLimit
+        }
+        Err(e) => {
+            eprintln!("error at step {}: {e}", sim.ticks());
+            Exit::SimulationError
+        }
+    };
+
+    match sim.output() {
+        Some(output) => println!("output: {output}"),
+        None => println!("output: none"),
+    }
+    if sim.mode == MatrixMode::L3X {
+        println!("output stream: {}", sim.output_stream().iter().join(","));
+    }
+    println!("steps: {}", sim.ticks());
+
+    if let Some(path) = args.trace {
+        if let Err(e) = write_trace(&sim, &path) {
+            eprintln!("error: could not write trace to {path}: {e}");
+        }
+    }
+
+    exit
+}
+
+/// Runs the command line interface if the program was given a command it recognizes. Returns
+/// `None` if the IDE should be opened instead, so that arguments added by a launcher are ignored.
+pub fn run_from_args() -> Option<ExitCode> {
+    let mut args = std::env::args().skip(1);
+
+    let exit = match args.next().as_deref() {
+        Some("run") => match parse_run_args(args) {
+            Ok(run_args) => run(run_args),
+            Err(e) => {
+                eprintln!("error: {e}\n\n{USAGE}");
+                Exit::BadInput
+            }
+        },
+        Some("help" | "--help" | "-h") => {
+            println!("{USAGE}");
+            Exit::Halted
+        }
+        _ => return None,
+    };
+    Some(exit.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<RunArgs, String> {
+        parse_run_args(args.iter().map(ToString::to_string))
+    }
+
+    #[test]
+    fn run_args() {
+        let run_args = args(&["prog.l3x", "--input", "2^5", "--stream", "2,4,8"]).unwrap();
+        assert_eq!(run_args.file, "prog.l3x");
+        assert_eq!(run_args.input, Registers::from(vec![(2, 5)]));
+        assert_eq!(
+            run_args.stream,
+            vec![
+                Registers::from(vec![(2, 1)]),
+                Registers::from(vec![(2, 2)]),
+                Registers::from(vec![(2, 3)])
+            ]
+        );
+        assert_eq!(run_args.mode, None);
+
+        assert!(args(&["prog.l3", "--input"]).is_err());
+        assert!(args(&["prog.l3", "--bogus"]).is_err());
+        assert!(args(&["--input", "5"]).is_err());
+    }
+}
@@ -0,0 +1,680 @@
+use std::{str::FromStr, sync::Mutex};
+
+use num_bigint::BigUint;
+
+use crate::registers::{parse_powers, RegisterParseError, Registers};
+
+/// Numbers which have already been factored, so that entering one again is instant
+static CACHE: Mutex<Cache> = Mutex::new(Cache::new());
+
+/// How many factorizations are kept in the cache
+const CACHE_SIZE: usize = 256;
+/// Primes below this are divided out before anything cleverer is tried
+const TRIAL_DIVISION_LIMIT: u64 = 1000;
+/// How many steps of Pollard's rho are taken before checking whether to keep going
+const ROUND: usize = 128;
+/// The bases tried by the Miller-Rabin test
+const MILLER_RABIN_BASES: [u32; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// The most recently used factorizations, least recently used first
+struct Cache(Vec<(BigUint, Registers)>);
+
+impl Cache {
+    const fn new() -> Self {
+        Cache(Vec::new())
+    }
+
+    fn get(&mut self, value: &BigUint) -> Option<Registers> {
+        let ix = self.0.iter().position(|(n, _)| n == value)?;
+        let entry = self.0.remove(ix);
+        let registers = entry.1.clone();
+        self.0.push(entry);
+        Some(registers)
+    }
+
+    fn insert(&mut self, value: BigUint, registers: Registers) {
+        self.0.retain(|(n, _)| *n != value);
+        if self.0.len() >= CACHE_SIZE {
+            self.0.remove(0);
+        }
+        self.0.push((value, registers));
+    }
+}
+
+fn gcd(mut a: BigUint, mut b: BigUint) -> BigUint {
+    let zero = BigUint::from(0u32);
+    while b != zero {
+        let rem = &a % &b;
+        a = std::mem::replace(&mut b, rem);
+    }
+    a
+}
+
+/// Pollard's rho method with Brent's cycle detection, in a form which can be paused after any
+/// round
+struct Rho {
+    n: BigUint,
+    offset: u64,
+    x: BigUint,
+    y: BigUint,
+    product: BigUint,
+    steps: usize,
+    next_save: usize,
+}
+
+impl Rho {
+    fn new(n: BigUint, offset: u64) -> Self {
+        Rho {
+            n,
+            offset,
+            x: BigUint::from(2u32),
+            y: BigUint::from(2u32),
+            product: BigUint::from(1u32),
+            steps: 0,
+            next_save: 1,
+        }
+    }
+
+    /// Takes a round of steps, returning a nontrivial factor of `n` if one turned up
+    fn round(&mut self) -> Option<BigUint> {
+        for _ in 0..ROUND {
+            self.x = (&self.x * &self.x + self.offset) % &self.n;
+            let diff = if self.x > self.y {
+                &self.x - &self.y
+            } else {
+                &self.y - &self.x
+            };
+            self.product = &self.product * diff % &self.n;
+            self.steps += 1;
+            if self.steps == self.next_save {
+                self.y = self.x.clone();
+                self.next_save *= 2;
+            }
+        }
+        let factor = gcd(self.product.clone(), self.n.clone());
+        if factor == self.n {
+            // every factor turned up at once, so try again with another polynomial
+            *self = Rho::new(self.n.clone(), self.offset + 1);
+            None
+        } else {
+            (factor != BigUint::from(1u32)).then_some(factor)
+        }
+    }
+}
+
+/// The Miller-Rabin test for odd numbers above [MILLER_RABIN_BASES], in a form which can be
+/// paused after any squaring
+struct PrimalityTest {
+    n: BigUint,
+    n_minus_one: BigUint,
+    /// `n - 1 = d * 2^s` with `d` odd
+    d: BigUint,
+    s: u64,
+    base: usize,
+    /// The base raised to the bits of `d` above `bit`, squared `squarings` times
+    x: BigUint,
+    bit: u64,
+    squarings: u64,
+}
+
+impl PrimalityTest {
+    fn new(n: BigUint) -> Self {
+        let n_minus_one = &n - 1u32;
+        let s = n_minus_one.trailing_zeros().unwrap_or(0);
+        let d = &n_minus_one >> s;
+        PrimalityTest {
+            bit: d.bits(),
+            x: BigUint::from(1u32),
+            base: 0,
+            squarings: 0,
+            n,
+            n_minus_one,
+            d,
+            s,
+        }
+    }
+
+    /// Takes a step, returning whether `n` is probably prime once that is known
+    fn step(&mut self) -> Option<bool> {
+        if self.bit > 0 {
+            self.bit -= 1;
+            self.x = &self.x * &self.x % &self.n;
+            if self.d.bit(self.bit) {
+                self.x = &self.x * MILLER_RABIN_BASES[self.base] % &self.n;
+            }
+            if self.bit > 0 {
+                return None;
+            }
+            if self.x == BigUint::from(1u32) {
+                return self.next_base();
+            }
+        } else {
+            self.x = &self.x * &self.x % &self.n;
+            self.squarings += 1;
+        }
+        if self.x == self.n_minus_one {
+            self.next_base()
+        } else if self.squarings + 1 >= self.s {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Moves on once the current base has been passed
+    fn next_base(&mut self) -> Option<bool> {
+        self.base += 1;
+        if self.base == MILLER_RABIN_BASES.len() {
+            return Some(true);
+        }
+        self.x = BigUint::from(1u32);
+        self.bit = self.d.bits();
+        self.squarings = 0;
+        None
+    }
+}
+
+/// A factorization which is worked on a little at a time, so that it can be stopped at any point
+struct Factorization {
+    primes: Vec<(u64, u64)>,
+    /// Small primes which have not been divided out yet
+    untried: Vec<u64>,
+    /// Factors which have not been split into primes yet
+    left: Vec<BigUint>,
+    test: Option<PrimalityTest>,
+    rho: Option<Rho>,
+}
+
+impl Factorization {
+    fn new(value: BigUint) -> Self {
+        Factorization {
+            primes: vec![],
+            untried: num_prime::nt_funcs::primes(TRIAL_DIVISION_LIMIT),
+            left: vec![value],
+            test: None,
+            rho: None,
+        }
+    }
+
+    /// How many digits the largest factor which is not known to be prime has
+    fn remaining_digits(&self) -> usize {
+        self.left
+            .iter()
+            .chain(self.test.as_ref().map(|test| &test.n))
+            .chain(self.rho.as_ref().map(|rho| &rho.n))
+            .map(|n| (n.bits() as f64 * std::f64::consts::LOG10_2).ceil() as usize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Works on the factorization for as long as `keep_going` allows, always doing at least a
+    /// little. Returns the result once it is done.
+    fn run(
+        &mut self,
+        keep_going: impl Fn() -> bool,
+    ) -> Option<Result<Registers, RegisterParseError>> {
+        loop {
+            if let Some(prime) = self.untried.pop() {
+                // every other factor waits until the small primes are divided out of the value
+                let value = &mut self.left[0];
+                let zero = BigUint::from(0u32);
+                let mut pow = 0;
+                while *value != zero && &*value % prime == zero {
+                    *value /= prime;
+                    pow += 1;
+                }
+                if pow > 0 {
+                    self.primes.push((prime, pow));
+                }
+            } else if let Some(ref mut rho) = self.rho {
+                if let Some(factor) = rho.round() {
+                    self.left.push(&rho.n / &factor);
+                    self.left.push(factor);
+                    self.rho = None;
+                }
+            } else if let Some(ref mut test) = self.test {
+                if let Some(prime) = test.step() {
+                    let n = self.test.take().unwrap().n;
+                    if prime {
+                        return Some(Err(RegisterParseError::FactorTooLarge));
+                    }
+                    self.rho = Some(Rho::new(n, 1));
+                }
+            } else {
+                let Some(n) = self.left.pop() else {
+                    let value = self
+                        .primes
+                        .iter()
+                        .fold(Registers::ONE, |acc, &(prime, pow)| {
+                            acc * Registers::from(vec![(prime, pow)])
+                        });
+                    return Some(Ok(value));
+                };
+                if let Ok(small) = u64::try_from(&n) {
+                    let factors = num_prime::nt_funcs::factorize64(small);
+                    self.primes
+                        .extend(factors.into_iter().map(|(prime, pow)| (prime, pow as u64)));
+                } else {
+                    self.test = Some(PrimalityTest::new(n));
+                }
+            }
+            if !keep_going() {
+                return None;
+            }
+        }
+    }
+}
+
+/// A value as entered, made up of numbers which may still need factoring
+#[derive(Clone, PartialEq, Debug)]
+pub struct Unfactored {
+    /// The part of the value which needs no factoring, such as one written as `{2:10, 3:5}`
+    known: Registers,
+    /// Each nonzero number still to be factored, with its power
+    numbers: Vec<(BigUint, u64)>,
+}
+
+impl From<Registers> for Unfactored {
+    fn from(known: Registers) -> Self {
+        Unfactored {
+            known,
+            numbers: vec![],
+        }
+    }
+}
+
+impl TryFrom<BigUint> for Unfactored {
+    type Error = RegisterParseError;
+
+    fn try_from(value: BigUint) -> Result<Self, Self::Error> {
+        Unfactored::from_powers(vec![(value, 1)])
+    }
+}
+
+/// Parses a value like [Registers::from_str], except that the numbers in it are left to be
+/// factored
+impl FromStr for Unfactored {
+    type Err = RegisterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with('{') {
+            s.parse::<Registers>().map(Unfactored::from)
+        } else {
+            Unfactored::from_powers(parse_powers(s)?)
+        }
+    }
+}
+
+impl Unfactored {
+    fn from_powers(numbers: Vec<(BigUint, u64)>) -> Result<Self, RegisterParseError> {
+        if numbers.iter().any(|(n, _)| *n == BigUint::from(0u32)) {
+            return Err(RegisterParseError::Zero);
+        }
+        Ok(Unfactored {
+            known: Registers::ONE,
+            numbers,
+        })
+    }
+
+    /// Raises the value to a power, or returns `None` if that takes a power of one of its numbers
+    /// beyond a u64
+    pub fn pow(&self, exp: u64) -> Option<Self> {
+        Some(Unfactored {
+            known: self.known.pow(exp),
+            numbers: self
+                .numbers
+                .iter()
+                .map(|(n, pow)| Some((n.clone(), pow.checked_mul(exp)?)))
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    /// The factorization, if it is already known
+    fn known(&self) -> Option<Registers> {
+        let mut cache = CACHE.lock().unwrap();
+        self.numbers
+            .iter()
+            .try_fold(self.known.clone(), |acc, (n, pow)| {
+                Some(acc * cache.get(n)?.pow(*pow))
+            })
+    }
+}
+
+/// Factors a list of values one after another, a little at a time
+struct Work {
+    values: std::vec::IntoIter<Unfactored>,
+    /// The value being worked on, as far as it has been factored, and the numbers left in it
+    value: Option<(Registers, std::vec::IntoIter<(BigUint, u64)>)>,
+    /// The number being factored, its power, and how far along it is
+    current: Option<(BigUint, u64, Factorization)>,
+    factored: Vec<Registers>,
+}
+
+impl Work {
+    fn new(values: Vec<Unfactored>) -> Self {
+        Work {
+            factored: Vec::with_capacity(values.len()),
+            values: values.into_iter(),
+            value: None,
+            current: None,
+        }
+    }
+
+    fn remaining_digits(&self) -> usize {
+        self.current
+            .as_ref()
+            .map_or(0, |(_, _, factorization)| factorization.remaining_digits())
+    }
+
+    /// Factors a number at once if it is known or small, or returns `None` if it has to be
+    /// worked on
+    fn factor_quickly(n: &BigUint) -> Option<Result<Registers, RegisterParseError>> {
+        // small numbers are factored at once, and would only crowd out the cache
+        match u64::try_from(n) {
+            Ok(small) => Some(Registers::try_from(small)),
+            Err(_) => CACHE.lock().unwrap().get(n).map(Ok),
+        }
+    }
+
+    /// Works on the values for as long as `keep_going` allows, always doing at least a little.
+    /// Returns the factorizations in order once they are all done, or the first error.
+    fn run(
+        &mut self,
+        keep_going: impl Fn() -> bool,
+    ) -> Option<Result<Vec<Registers>, RegisterParseError>> {
+        loop {
+            if let Some((_, _, ref mut factorization)) = self.current {
+                let result = factorization.run(&keep_going)?;
+                let (n, pow, _) = self.current.take().unwrap();
+                match result {
+                    Ok(registers) => {
+                        CACHE.lock().unwrap().insert(n, registers.clone());
+                        if let Some((ref mut acc, _)) = self.value {
+                            *acc *= &registers.pow(pow);
+                        }
+                    }
+                    Err(e) => return Some(Err(e)),
+                }
+            } else if let Some((ref mut acc, ref mut numbers)) = self.value {
+                match numbers.next() {
+                    Some((n, pow)) => match Work::factor_quickly(&n) {
+                        Some(Ok(registers)) => *acc *= &registers.pow(pow),
+                        Some(Err(e)) => return Some(Err(e)),
+                        None => {
+                            let factorization = Factorization::new(n.clone());
+                            self.current = Some((n, pow, factorization));
+                        }
+                    },
+                    None => {
+                        let (value, _) = self.value.take().unwrap();
+                        self.factored.push(value);
+                    }
+                }
+            } else {
+                match self.values.next() {
+                    Some(value) => self.value = Some((value.known, value.numbers.into_iter())),
+                    None => return Some(Ok(std::mem::take(&mut self.factored))),
+                }
+            }
+            if !keep_going() {
+                return None;
+            }
+        }
+    }
+}
+
+/// Factors values without holding up the frame loop. Native builds factor on another thread as
+/// soon as this is created; web builds have no threads, so the work is done whenever this is
+/// polled. Dropping it cancels the factorization.
+pub struct Factoring {
+    total: usize,
+    result: Option<Result<Vec<Registers>, RegisterParseError>>,
+    #[cfg(target_arch = "wasm32")]
+    work: Work,
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: std::sync::mpsc::Receiver<Result<Vec<Registers>, RegisterParseError>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    factored: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    remaining_digits: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Factoring {
+    /// Starts factoring the values, unless they are all known already
+    pub fn start(values: Vec<Unfactored>) -> Self {
+        let total = values.len();
+        // stops looking at the first value which is not known, so long lists cost little here
+        let result = values
+            .iter()
+            .map(Unfactored::known)
+            .collect::<Option<_>>()
+            .map(Ok);
+        let work = Work::new(values);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use std::sync::{
+                atomic::{AtomicBool, AtomicUsize, Ordering},
+                Arc,
+            };
+
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let factored = Arc::new(AtomicUsize::new(0));
+            let remaining_digits = Arc::new(AtomicUsize::new(0));
+            let cancelled = Arc::new(AtomicBool::new(false));
+            if result.is_none() {
+                let (factored, remaining_digits, cancelled) = (
+                    factored.clone(),
+                    remaining_digits.clone(),
+                    cancelled.clone(),
+                );
+                let mut work = work;
+                std::thread::spawn(move || {
+                    while !cancelled.load(Ordering::Relaxed) {
+                        if let Some(result) = work.run(|| false) {
+                            // the receiver is gone if the factorization was cancelled
+                            sender.send(result).ok();
+                            break;
+                        }
+                        factored.store(work.factored.len(), Ordering::Relaxed);
+                        remaining_digits.store(work.remaining_digits(), Ordering::Relaxed);
+                    }
+                });
+            }
+            Self {
+                total,
+                result,
+                receiver,
+                factored,
+                remaining_digits,
+                cancelled,
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        Self {
+            total,
+            result,
+            work,
+        }
+    }
+
+    /// Parses a single value like [Registers::from_str], except that plain numbers are factored
+    /// in the background
+    pub fn parse(s: &str) -> Result<Self, RegisterParseError> {
+        Ok(Self::start(vec![s.parse()?]))
+    }
+
+    /// Checks on the factorization. On web builds, works on it for as long as `keep_going`
+    /// allows. Returns the factorizations in the order the values were given once they are done.
+    pub fn poll(
+        &mut self,
+        #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))] keep_going: impl Fn() -> bool,
+    ) -> Option<&Result<Vec<Registers>, RegisterParseError>> {
+        if self.result.is_none() {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                self.result = self.receiver.try_recv().ok();
+            }
+            #[cfg(target_arch = "wasm32")]
+            {
+                self.result = self.work.run(keep_going);
+            }
+        }
+        self.result.as_ref()
+    }
+
+    /// How many of the values have been factored so far, and how many there are in all
+    pub fn progress(&self) -> (usize, usize) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let factored = self.factored.load(std::sync::atomic::Ordering::Relaxed);
+        #[cfg(target_arch = "wasm32")]
+        let factored = self.work.factored.len();
+        (factored, self.total)
+    }
+
+    /// How many digits the largest factor of the current value which is not known to be prime has
+    pub fn remaining_digits(&self) -> usize {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self
+            .remaining_digits
+            .load(std::sync::atomic::Ordering::Relaxed);
+        #[cfg(target_arch = "wasm32")]
+        return self.work.remaining_digits();
+    }
+
+    /// Waits for the factorization to finish
+    #[cfg(test)]
+    pub fn wait(mut self) -> Result<Vec<Registers>, RegisterParseError> {
+        loop {
+            if let Some(result) = self.poll(|| true) {
+                return result.clone();
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Factoring {
+    fn drop(&mut self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factor(text: &str) -> Result<Registers, RegisterParseError> {
+        match Factoring::parse(text) {
+            Ok(factoring) => factoring.wait().map(|mut values| values.pop().unwrap()),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[test]
+    fn factoring() {
+        // three primes near 10^9, too large together for a u64
+        let semiprime = "998244368971909710889394239";
+        let expected = Registers::from(vec![
+            (998_244_353, 1),
+            (1_000_000_007, 1),
+            (1_000_000_009, 1),
+        ]);
+        assert_eq!(factor(semiprime), Ok(expected.clone()));
+        assert_eq!(
+            Factoring::parse(semiprime).unwrap().poll(|| false),
+            Some(&Ok(vec![expected])),
+            "answered from the cache"
+        );
+
+        assert_eq!(
+            factor("3541774862152233910272"),
+            Ok(Registers::from(vec![(2, 70), (3, 1)]))
+        );
+        assert_eq!(
+            factor("2^10 * 3"),
+            Ok(Registers::from(vec![(2, 10), (3, 1)]))
+        );
+        assert_eq!(factor("1"), Ok(Registers::ONE));
+        assert_eq!(factor("0"), Err(RegisterParseError::Zero));
+        // 2^89 - 1 is prime
+        assert_eq!(
+            factor("618970019642690137449562111"),
+            Err(RegisterParseError::FactorTooLarge)
+        );
+    }
+
+    #[test]
+    fn lists() {
+        let values = ["12", "2^3 * 5", "998244368971909710889394239", "7"]
+            .map(|text| text.parse().unwrap())
+            .to_vec();
+        let factoring = Factoring::start(values);
+        assert_eq!(factoring.progress().1, 4);
+        assert_eq!(
+            factoring.wait(),
+            Ok(vec![
+                Registers::from(vec![(2, 2), (3, 1)]),
+                Registers::from(vec![(2, 3), (5, 1)]),
+                Registers::from(vec![
+                    (998_244_353, 1),
+                    (1_000_000_007, 1),
+                    (1_000_000_009, 1)
+                ]),
+                Registers::from(vec![(7, 1)]),
+            ])
+        );
+        assert_eq!("0".parse::<Unfactored>(), Err(RegisterParseError::Zero));
+        assert_eq!(
+            "2 * 0^3".parse::<Unfactored>(),
+            Err(RegisterParseError::Zero)
+        );
+
+        // the bases of a product are factored in the background too
+        assert_eq!(
+            factor("998244368971909710889394239^2 * 6"),
+            Ok(Registers::from(vec![
+                (2, 1),
+                (3, 1),
+                (998_244_353, 2),
+                (1_000_000_007, 2),
+                (1_000_000_009, 2)
+            ]))
+        );
+    }
+
+    #[test]
+    fn primality() {
+        let is_prime = |n: &str| {
+            let mut test = PrimalityTest::new(n.parse().unwrap());
+            loop {
+                if let Some(prime) = test.step() {
+                    return prime;
+                }
+            }
+        };
+        // 2^89 - 1 and 2^127 - 1 are prime
+        assert!(is_prime("618970019642690137449562111"));
+        assert!(is_prime("170141183460469231731687303715884105727"));
+        assert!(!is_prime("998244368971909710889394239"));
+        // a strong pseudoprime to every base up to 23
+        assert!(!is_prime("3825123056546413051"));
+    }
+
+    #[test]
+    fn cache_forgets_least_recently_used() {
+        let mut cache = Cache::new();
+        for n in 0..CACHE_SIZE as u32 {
+            cache.insert(BigUint::from(n), Registers::ONE);
+        }
+        assert!(cache.get(&BigUint::from(0u32)).is_some());
+        cache.insert(BigUint::from(1000u32), Registers::ONE);
+        assert_eq!(cache.0.len(), CACHE_SIZE);
+        assert!(cache.get(&BigUint::from(0u32)).is_some(), "used recently");
+        assert!(cache.get(&BigUint::from(1u32)).is_none(), "forgotten");
+    }
+}
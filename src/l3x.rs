@@ -24,12 +24,13 @@ macro_rules! arrayvec {
 
 #[derive(PartialEq, Eq, Debug,Clone)]
 pub struct L3X {
-    // TODO support watch points
     pub direction: Direction,
     pub command: L3XCommand,
+    /// Marked by a trailing `;`. The IDE pauses whenever a number enters a watched cell.
+    pub watch: bool,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy, strum_macros::EnumIter)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, strum_macros::EnumIter)]
 #[rustfmt::skip]
 pub enum Direction {
     Up, Down, Left, Right,
@@ -191,6 +192,11 @@ impl TryFrom<&str> for MaybeL3X {
             return Ok(Self::None);
         }
 
+        let (value, watch) = match value.strip_suffix(';') {
+            Some(value) => (value.trim_end(), true),
+            None => (value, false),
+        };
+
         let direction_char = value.chars().last().ok_or(L3XParseError::WrongLength)?;
         let direction: Direction = direction_char.try_into()?;
 
@@ -226,28 +232,65 @@ impl TryFrom<&str> for MaybeL3X {
             }
         };
 
-        Ok(Self::Some(L3X { direction, command }))
+        Ok(Self::Some(L3X {
+            direction,
+            command,
+            watch,
+        }))
     }
 }
 
-impl ToString for L3X {
-    fn to_string(&self) -> String {
+/// Which letters directions are written with
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum DirectionLetters {
+    #[default]
+    Udlr,
+    Nsew,
+}
+
+/// How cells are spelled when a program is written out
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct CellStyle {
+    pub letters: DirectionLetters,
+    pub lowercase: bool,
+}
+
+impl L3X {
+    pub fn to_styled_string(&self, style: CellStyle) -> String {
         let mut out = match self.command {
             L3XCommand::Multiply(ref n) => format!("{n}"),
             L3XCommand::Duplicate => "%".to_string(),
             L3XCommand::Queue => "&".to_string(),
             L3XCommand::Annihilate => '~'.to_string(),
         };
-        out.push(match self.direction {
-            Direction::Up => 'U',
-            Direction::Down => 'D',
-            Direction::Left => 'L',
-            Direction::Right => 'R',
+        let letter = match (style.letters, self.direction) {
+            (DirectionLetters::Udlr, Direction::Up) => 'U',
+            (DirectionLetters::Udlr, Direction::Down) => 'D',
+            (DirectionLetters::Udlr, Direction::Left) => 'L',
+            (DirectionLetters::Udlr, Direction::Right) => 'R',
+            (DirectionLetters::Nsew, Direction::Up) => 'N',
+            (DirectionLetters::Nsew, Direction::Down) => 'S',
+            (DirectionLetters::Nsew, Direction::Left) => 'W',
+            (DirectionLetters::Nsew, Direction::Right) => 'E',
+        };
+        out.push(if style.lowercase {
+            letter.to_ascii_lowercase()
+        } else {
+            letter
         });
+        if self.watch {
+            out.push(';');
+        }
         out
     }
 }
 
+impl ToString for L3X {
+    fn to_string(&self) -> String {
+        self.to_styled_string(CellStyle::default())
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum Output {
     Major(Direction),
@@ -515,6 +558,10 @@ impl L3X {
             font_size,
             primary_color,
         );
+        if self.watch {
+            let marker = lower + vec2(0.85, 0.15) * cell_size;
+            draw_circle(marker.x, marker.y, cell_size * 0.08, ORANGE);
+        }
         let minor_color = RED;
 
         let out_arrow_vertices = vec![
@@ -626,8 +673,20 @@ mod tests {
             L3X::try_from("3L"),
             Ok(L3X {
                 direction: Direction::Left,
-                command: L3XCommand::Multiply(Registers([(3, 1)].into_iter().collect()))
+                command: L3XCommand::Multiply(Registers::from(vec![(3, 1)])),
+                watch: false,
             })
         )
     }
+
+    #[test]
+    fn watch_points() {
+        let watched = L3X::try_from("%u ;").unwrap();
+        assert!(watched.watch);
+        assert_eq!(watched.command, L3XCommand::Duplicate);
+        assert_eq!(watched.to_string(), "%U;");
+        assert!(!L3X::try_from("2R").unwrap().watch);
+        assert_eq!(L3X::try_from(";"), Err(L3XParseError::WrongLength));
+        assert_eq!(L3X::try_from("2;R"), Err(L3XParseError::BadCommand));
+    }
 }
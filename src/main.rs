@@ -10,13 +10,24 @@ use wasync::AsyncContext;
 use crate::input::InputDriver;
 use crate::matrix::{Matrix};
 
+mod batch;
+mod breakpoint;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+mod factorize;
 mod input;
 mod l3x;
 mod matrix;
 mod polygon;
 mod registers;
+mod ruleset;
+mod simulator;
 mod sound;
 mod swapbuffer;
+mod symbolic;
+mod testcase;
+mod timeline;
+mod trace;
 mod traveler;
 #[cfg(target_arch = "wasm32")]
 mod wasm_log;
@@ -59,8 +70,17 @@ impl<'a> Model<'a> {
     }
 }
 
-#[macroquad::main("L3X IDE")]
-async fn main() {
+fn main() -> std::process::ExitCode {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(exit) = cli::run_from_args() {
+        return exit;
+    }
+
+    macroquad::Window::new("L3X IDE", ide());
+    std::process::ExitCode::SUCCESS
+}
+
+async fn ide() {
     #[cfg(not(target_arch = "wasm32"))]
     {
         if let Err(e) = simple_logger::SimpleLogger::default()
@@ -109,10 +129,10 @@ async fn main() {
         //It fixes the bug, but I am still not sure of the root cause
         if let Some(pos) = state.input_driver.lmb().started_holding() {
             let corner_position =
-                (state.offset + state.matrix.dims.as_vec2() * CELL_SIZE) * state.scale;
+                (state.offset + state.matrix.dims().as_vec2() * CELL_SIZE) * state.scale;
             const ALLOWED_DISTANCE: f32 = 15.;
             if corner_position.distance_squared(physical) < (ALLOWED_DISTANCE * state.scale).powi(2) {
-                state.resizing_matrix = Some(state.matrix.dims);
+                state.resizing_matrix = Some(state.matrix.dims());
             } else if physical.cmpgt(state.offset).all() && physical.cmplt(corner_position).all() {
                 state
                     .matrix
@@ -168,7 +188,8 @@ async fn main() {
 
         state.matrix.update(&mut state.ctx);
 
-        state.matrix.draw(state.offset, CELL_SIZE, state.scale);
+        let hovered = (!egui_hovered).then(|| logical.floor().as_ivec2());
+        state.matrix.draw(state.offset, CELL_SIZE, state.scale, hovered);
         egui_macroquad::draw();
         next_frame().await
     }
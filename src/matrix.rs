@@ -1,63 +1,44 @@
-use itertools::Itertools;
 use macroquad::prelude::*;
-use smallvec::{smallvec, SmallVec};
-use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    ops::Index,
-};
+use std::{collections::HashMap, ops::Index};
 use tap::Tap;
-use vec_drain_where::VecDrainWhereExt;
 
 mod file;
+mod palette;
+mod project;
 mod ui;
 use crate::{
-    l3x::{Direction, L3XCommand, MaybeL3X, L3X},
-    registers::Registers,
+    l3x::{CellStyle, Direction, L3XCommand, MaybeL3X, L3X},
+    registers::DisplayMode,
+    simulator::Simulator,
     sound::chord::Chord,
-    swapbuffer::SwapBuffer,
-    traveler::Traveler,
+    timeline::Timeline,
     wasync::AsyncContext,
 };
 use core::cmp::{max, min};
 
-use self::ui::{UiSingleInput, UiStreamInput};
-
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
-pub enum MatrixMode {
-    #[default]
-    L3,
-    L3X,
-}
+pub use crate::simulator::MatrixMode;
 
-bitflags::bitflags! {
-    #[derive(Copy, Clone)]
-    struct Alignments: u8 {
-        const ALIGNED = 0b01;
-        const UNALIGNED = 0b10;
-    }
-}
+const ERROR_HIGHLIGHT: Color = Color::new(0.9, 0.16, 0.22, 0.4);
+const VIOLATION_HIGHLIGHT: Color = Color::new(1.0, 0.8, 0.0, 0.4);
+const HEATMAP_HOT: Color = Color::new(1.0, 0.35, 0.0, 0.7);
+const COMMENT_MARKER: Color = Color::new(0.2, 0.45, 0.85, 0.9);
+const COMMENT_BACKGROUND: Color = Color::new(1.0, 0.98, 0.85, 0.95);
+/// How long each frame may spend simulating while running to the end of a program, in seconds.
+/// The grid is only redrawn between frames, so this also sets how often it is redrawn.
+const RUN_TO_END_FRAME_BUDGET: f64 = 0.1;
+pub use file::read_program;
 
-impl Alignments {
-    fn aligned(one: Direction, the_other: Direction) -> Self {
-        if one == the_other {
-            Self::ALIGNED
-        } else {
-            Self::UNALIGNED
-        }
-    }
-}
+use self::file::{ImportReport, SourceLayout};
+use self::palette::CellColor;
+use self::ui::{UiBatch, UiBreakpoints, UiSingleInput, UiStreamInput, UiSymbolic, UiTestSuite};
 
-impl MatrixMode {
-    fn minimum_size(&self) -> UVec2 {
-        match self {
-            MatrixMode::L3 => uvec2(1, 1),
-            MatrixMode::L3X => uvec2(2, 2),
-        }
-    }
-}
 #[derive(Clone)]
 struct L3XData {
     data: Vec<Vec<MaybeL3X>>,
+    /// The background color of each cell, laid out like `data`
+    colors: Vec<Vec<Option<CellColor>>>,
+    /// Comments on areas which lie entirely within the data, relative to its top left corner
+    comments: Vec<(Selection, String)>,
     dims: UVec2,
 }
 impl Index<UVec2> for L3XData {
@@ -86,7 +67,7 @@ impl MatrixAction {
     fn inverse(&self, current_state: &mut Matrix) -> MatrixAction {
         match self {
             //put the size back to what it was
-            Resize(_) => Resize(current_state.dims),
+            Resize(_) => Resize(current_state.sim.dims),
             //paste back what used to be in that spot
             Paste(start, data) => Paste(
                 *start,
@@ -106,7 +87,7 @@ impl MatrixAction {
 }
 
 /// Defines the selected area on which to operate, start and end inclusive
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Selection {
     starts: IVec2,
     ends: IVec2,
@@ -139,6 +120,10 @@ impl Selection {
         self.starts.cmple(location).all() && self.ends.cmpge(location).all()
     }
 
+    fn contains_area(&self, area: &Selection) -> bool {
+        self.contains(area.starts) && self.contains(area.ends)
+    }
+
     fn to(self, new_start: IVec2) -> Self {
         Self {
             starts: new_start,
@@ -153,63 +138,90 @@ impl Selection {
     fn height(&self) -> i32 {
         self.ends.y - self.starts.y + 1
     }
+
+    /// The name a spreadsheet would give to the area, such as `B2` or `B2:C4`
+    fn name(&self) -> String {
+        if self.starts == self.ends {
+            file::cell_name(self.starts)
+        } else {
+            format!("{}:{}", file::cell_name(self.starts), file::cell_name(self.ends))
+        }
+    }
 }
 
 pub struct Matrix {
-    mode: MatrixMode,
-    instructions: HashMap<IVec2, L3X>,
-    pub dims: UVec2,
+    sim: Simulator,
+    timeline: Timeline,
     selecting: Option<Selection>,
     selecting_text: String,
     period: usize,
     stepping: bool,
+    running_to_end: bool,
 
     time: usize,
 
-    queues: HashMap<IVec2, VecDeque<Registers>>,
-    waiting_for_queue: Vec<(Traveler, Registers)>,
-    travelers: SwapBuffer<Traveler>,
-    output: Option<Registers>,
-    output_stream: Vec<Registers>,
-
     focus_editing: bool,
 
     single_input: UiSingleInput,
     stream_input: UiStreamInput,
+    breakpoints: UiBreakpoints,
+    test_suite: UiTestSuite,
+    batch: UiBatch,
+    symbolic: UiSymbolic,
 
     simulating: bool,
     sound_follows_cursor: bool,
     global_volume: u8,
     gridlines: bool,
+    heatmap: bool,
+    /// How values are shown in the panels
+    display_mode: DisplayMode,
     history: Vec<MatrixAction>,
     copy_data: Option<L3XData>,
+    import_report: Option<ImportReport>,
+    /// How the current program was laid out in the file it was imported from
+    source: Option<SourceLayout>,
+    export_style: CellStyle,
+    /// Anything the user wants to remember about the program, saved in project files
+    notes: String,
+    /// Free text attached to single cells or rectangles of cells, also saved in project files
+    comments: HashMap<Selection, String>,
+    /// Background colors of cells, saved in project files but not in csv programs
+    colors: HashMap<IVec2, CellColor>,
 }
 
 impl Default for Matrix {
     fn default() -> Self {
         Self {
-            mode: Default::default(),
-            instructions: Default::default(),
-            dims: uvec2(1, 1),
+            sim: Default::default(),
+            timeline: Default::default(),
             selecting: Default::default(),
             selecting_text: Default::default(),
             period: 10,
             stepping: false,
-            queues: Default::default(),
-            waiting_for_queue: Default::default(),
-            travelers: Default::default(),
-            output: Default::default(),
-            output_stream: Default::default(),
+            running_to_end: false,
             focus_editing: false,
             single_input: Default::default(),
             stream_input: Default::default(),
+            breakpoints: Default::default(),
+            test_suite: Default::default(),
+            batch: Default::default(),
+            symbolic: Default::default(),
             simulating: false,
             sound_follows_cursor: false,
             global_volume: 80,
             gridlines: false,
+            display_mode: DisplayMode::default(),
+            heatmap: false,
             time: 0,
             history: vec![],
             copy_data: None,
+            import_report: None,
+            source: None,
+            export_style: Default::default(),
+            notes: String::new(),
+            comments: HashMap::new(),
+            colors: HashMap::new(),
         }
     }
 }
@@ -217,7 +229,9 @@ impl Default for Matrix {
 impl Matrix {
     pub fn update(&mut self, ctx: &mut AsyncContext) {
         self.time += 1;
-        if self.time > self.period {
+        if self.running_to_end {
+            self.run_to_end_for_frame();
+        } else if self.time > self.period {
             self.time %= self.period;
             if self.stepping {
                 self.step();
@@ -227,7 +241,8 @@ impl Matrix {
         ctx.try_export_file();
     }
 
-    pub fn draw(&self, offset: Vec2, cell_size: f32, scale: f32) {
+    /// Draws the matrix, along with the comments on the `hovered` cell if there is one
+    pub fn draw(&self, offset: Vec2, cell_size: f32, scale: f32, hovered: Option<IVec2>) {
         let primary_color = DARKBROWN;
 
         let cell_size = cell_size * scale;
@@ -237,13 +252,14 @@ impl Matrix {
         // annotate input and output
         let io_text_offset = vec2(0.4, 0.67) * cell_size;
         let i_single = offset + vec2(0.0, -cell_size) + io_text_offset;
-        let o_single = offset + (self.dims - uvec2(1, 0)).as_vec2() * cell_size + io_text_offset;
+        let o_single =
+            offset + (self.sim.dims - uvec2(1, 0)).as_vec2() * cell_size + io_text_offset;
         draw_text("I", i_single.x, i_single.y, font_size, primary_color);
         draw_text("O", o_single.x, o_single.y, font_size, primary_color);
-        if self.mode == MatrixMode::L3X {
+        if self.sim.mode == MatrixMode::L3X {
             let i_stream = offset + vec2(cell_size, -cell_size) + io_text_offset;
             let o_stream =
-                offset + (self.dims - uvec2(2, 0)).as_vec2() * cell_size + io_text_offset;
+                offset + (self.sim.dims - uvec2(2, 0)).as_vec2() * cell_size + io_text_offset;
             draw_text("I_s", i_stream.x, i_stream.y, font_size, primary_color);
             draw_text("O_s", o_stream.x, o_stream.y, font_size, primary_color);
         }
@@ -254,34 +270,81 @@ impl Matrix {
             draw_rectangle(r.x, r.y, r.w, r.h, LIGHTGRAY)
         }
 
+        // cell colors are see-through, so that the selection still shows under them
+        for (cell, color) in &self.colors {
+            if cell.cmplt(self.sim.dims.as_ivec2()).all() {
+                let lower = cell.as_vec2() * cell_size + offset;
+                draw_rectangle(lower.x, lower.y, cell_size, cell_size, color.color());
+            }
+        }
+
+        // shade each cell by how often it has been visited, on a log scale so that cells in tight
+        // loops do not wash out everything else
+        if self.heatmap {
+            let stats = self.sim.cell_stats();
+            let hottest = stats.values().map(|s| s.visits).max().unwrap_or(0).max(1);
+            for (cell, stats) in stats {
+                let heat = (stats.visits as f32).ln_1p() / (hottest as f32).ln_1p();
+                let lower = cell.as_vec2() * cell_size + offset;
+                let color = Color {
+                    a: HEATMAP_HOT.a * heat,
+                    ..HEATMAP_HOT
+                };
+                draw_rectangle(lower.x, lower.y, cell_size, cell_size, color);
+            }
+        }
+
+        // highlight the cells which break the ruleset, those which could not be imported, and those
+        // responsible for a simulation error
+        for cell in self.sim.rule_violations().iter().flat_map(|v| v.cells()) {
+            let lower = cell.as_vec2() * cell_size + offset;
+            draw_rectangle(lower.x, lower.y, cell_size, cell_size, VIOLATION_HIGHLIGHT);
+        }
+        if let Some(ImportReport {
+            issues,
+            pending: None,
+        }) = &self.import_report
+        {
+            for issue in issues {
+                let lower = issue.location.as_vec2() * cell_size + offset;
+                draw_rectangle(lower.x, lower.y, cell_size, cell_size, ERROR_HIGHLIGHT);
+            }
+        }
+        if let Some(error) = self.sim.error() {
+            for cell in error.cells() {
+                let lower = cell.as_vec2() * cell_size + offset;
+                draw_rectangle(lower.x, lower.y, cell_size, cell_size, ERROR_HIGHLIGHT);
+            }
+        }
+
         // box around the matrix
         draw_rectangle_lines(
             offset.x,
             offset.y,
-            self.dims.x as f32 * cell_size,
-            self.dims.y as f32 * cell_size,
+            self.sim.dims.x as f32 * cell_size,
+            self.sim.dims.y as f32 * cell_size,
             4.0,
             primary_color,
         );
 
         if self.gridlines {
-            for column in 1..self.dims.x {
+            for column in 1..self.sim.dims.x {
                 let lower = vec2(column as f32, 0.) * cell_size + offset;
-                let upper = lower + vec2(0., self.dims.y as f32) * cell_size;
+                let upper = lower + vec2(0., self.sim.dims.y as f32) * cell_size;
                 draw_line(lower.x, lower.y, upper.x, upper.y, 2.0, primary_color)
             }
-            for row in 1..self.dims.y {
+            for row in 1..self.sim.dims.y {
                 let lower = vec2(0., row as f32) * cell_size + offset;
-                let upper = lower + vec2(self.dims.x as f32, 0.) * cell_size;
+                let upper = lower + vec2(self.sim.dims.x as f32, 0.) * cell_size;
                 draw_line(lower.x, lower.y, upper.x, upper.y, 2.0, primary_color)
             }
         }
 
-        for (location, instruction) in &self.instructions {
-            if location.cmplt(self.dims.as_ivec2()).all() {
+        for (location, instruction) in &self.sim.instructions {
+            if location.cmplt(self.sim.dims.as_ivec2()).all() {
                 instruction.draw(
-                    &self.instructions,
-                    self.dims,
+                    &self.sim.instructions,
+                    self.sim.dims,
                     *location,
                     cell_size,
                     offset,
@@ -291,11 +354,76 @@ impl Matrix {
             }
         }
 
+        // mark each commented area with a triangle in its top right corner, like a spreadsheet
+        for area in self.comments.keys() {
+            let r = area.rect(offset, cell_size);
+            let marker = 0.2 * cell_size;
+            draw_triangle(
+                vec2(r.right() - marker, r.y),
+                vec2(r.right(), r.y),
+                vec2(r.right(), r.y + marker),
+                COMMENT_MARKER,
+            );
+            if area.starts != area.ends {
+                draw_rectangle_lines(r.x, r.y, r.w, r.h, 2.0 * scale, COMMENT_MARKER);
+            }
+        }
+
         // draw travelers
-        for traveler in &**self.travelers {
+        for traveler in self.sim.travelers() {
             let pos = (traveler.location.as_vec2() + Vec2::splat(0.5)) * cell_size + offset;
             draw_circle(pos.x, pos.y, 10.0 * scale, BLUE);
         }
+        if let Some(error) = self.sim.error() {
+            for traveler in error.travelers() {
+                let pos = (traveler.location.as_vec2() + Vec2::splat(0.5)) * cell_size + offset;
+                draw_circle_lines(pos.x, pos.y, 14.0 * scale, 3.0 * scale, RED);
+            }
+        }
+
+        if let Some(hovered) = hovered {
+            self.draw_comment_tooltip(hovered, offset, cell_size, scale);
+        }
+    }
+
+    /// Shows every comment on a cell in a box just to its right
+    fn draw_comment_tooltip(&self, location: IVec2, offset: Vec2, cell_size: f32, scale: f32) {
+        let mut areas = self
+            .comments
+            .iter()
+            .filter(|(area, _)| area.contains(location))
+            .collect::<Vec<_>>();
+        areas.sort_by_key(|(area, _)| (area.starts.y, area.starts.x, area.ends.y, area.ends.x));
+        let lines = areas
+            .into_iter()
+            .flat_map(|(_, text)| text.lines())
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return;
+        }
+
+        let font_size = 20.0 * scale;
+        let padding = 6.0 * scale;
+        let width = lines
+            .iter()
+            .map(|line| measure_text(line, None, font_size as u16, 1.0).width)
+            .fold(0.0, f32::max);
+        let corner = (location + ivec2(1, 0)).as_vec2() * cell_size + offset;
+        draw_rectangle(
+            corner.x,
+            corner.y,
+            width + 2.0 * padding,
+            lines.len() as f32 * font_size + 2.0 * padding,
+            COMMENT_BACKGROUND,
+        );
+        for (ix, line) in lines.iter().enumerate() {
+            let baseline = corner.y + padding + (ix as f32 + 0.75) * font_size;
+            draw_text(line, corner.x + padding, baseline, font_size, DARKBROWN);
+        }
+    }
+
+    pub fn dims(&self) -> UVec2 {
+        self.sim.dims
     }
 
     pub fn global_volume(&self) -> f32 {
@@ -304,7 +432,8 @@ impl Matrix {
 
     pub fn update_sound(&self, logical_mouse: Vec2) -> Option<Chord> {
         if self.sound_follows_cursor {
-            self.travelers
+            self.sim
+                .travelers()
                 .iter()
                 .map(|traveler| {
                     let dist = traveler.location.as_vec2().distance(logical_mouse);
@@ -321,7 +450,8 @@ impl Matrix {
                 })
         } else {
             Some(
-                self.travelers
+                self.sim
+                    .travelers()
                     .iter()
                     .map(|traveler| Chord {
                         volume: self.global_volume(),
@@ -334,19 +464,21 @@ impl Matrix {
 
     /// Forces the streaming input square to be a queue when the matrix is in l3x mode
     fn force_queue_l3x(&mut self) {
-        if self.mode == MatrixMode::L3X {
-            self.instructions
+        if self.sim.mode == MatrixMode::L3X {
+            self.sim
+                .instructions
                 .entry(ivec2(1, 0))
                 .and_modify(|e| e.command = L3XCommand::Queue)
                 .or_insert(L3X {
                     direction: Direction::Down,
                     command: L3XCommand::Queue,
+                    watch: false,
                 });
         }
     }
 
     fn is_editing_input_stream(&self) -> bool {
-        self.mode == MatrixMode::L3X
+        self.sim.mode == MatrixMode::L3X
             && self
                 .selecting
                 .map(|u| u.contains(ivec2(1, 0)))
@@ -354,8 +486,8 @@ impl Matrix {
     }
 
     pub fn set_dims(&mut self, dims: IVec2) {
-        if !self.simulating && self.mode.minimum_size().as_ivec2().cmple(dims).all() {
-            self.dims = dims.as_uvec2();
+        if !self.simulating && self.sim.mode.minimum_size().as_ivec2().cmple(dims).all() {
+            self.sim.dims = dims.as_uvec2();
         }
     }
 
@@ -369,12 +501,13 @@ impl Matrix {
 
     pub fn edit(&mut self, location: Selection) {
         if location.starts.cmpge(IVec2::ZERO).all()
-            && location.ends.cmplt(self.dims.as_ivec2()).all()
+            && location.ends.cmplt(self.sim.dims.as_ivec2()).all()
         {
             let location = location;
             self.focus_editing = true;
             self.selecting = Some(location);
             self.selecting_text = self
+                .sim
                 .instructions
                 .get(&location.starts)
                 .map(|l3x| l3x.to_string())
@@ -383,13 +516,21 @@ impl Matrix {
     }
 
     pub fn transpose(&mut self) {
-        self.dims = self.dims.yx();
+        self.sim.dims = self.sim.dims.yx();
         let instructions_new: HashMap<_, _> = self
+            .sim
             .instructions
             .drain()
             .map(|(k, v)| (k.yx(), v.tap_mut(|v| v.direction = v.direction.opposite())))
             .collect();
-        self.instructions = instructions_new;
+        self.sim.instructions = instructions_new;
+        self.colors = self.colors.drain().map(|(k, v)| (k.yx(), v)).collect();
+        self.remap_comments(|area| {
+            Some(Selection {
+                starts: area.starts.yx(),
+                ends: area.ends.yx(),
+            })
+        });
         if let Some(selecting) = self.selecting {
             self.edit(selecting.transpose())
         }
@@ -404,186 +545,160 @@ impl Matrix {
         self.apply(MatrixAction::Resize(final_size));
     }
 
-    fn init_simulation_inner(&mut self) -> Option<()> {
-        self.travelers.push(Traveler {
-            value: self.single_input.value().clone(),
-            location: IVec2::ZERO,
-            direction: Direction::Down,
-        });
-
-        self.queues
-            .insert(ivec2(1, 0), self.stream_input.value().clone().into());
-
-        Some(())
-    }
-
     fn init_simulation(&mut self) {
-        if self.mode == MatrixMode::L3
-            || self.instructions[&ivec2(1, 0)].command == L3XCommand::Queue
-        {
-            self.simulating = self.init_simulation_inner().is_some()
-        } else {
-            log::warn!("Could not start simulation: There is no queue on the queue input square!");
+        let input = self.single_input.value().clone();
+        let stream = self.stream_input.value().clone();
+        match self.sim.start(input, stream) {
+            Ok(()) => {
+                self.simulating = true;
+                self.timeline.record_now(&self.sim);
+            }
+            Err(e) => log::warn!("Could not start simulation: {e}"),
         }
     }
 
     fn cleanup_simulation(&mut self) {
         self.simulating = false;
-        self.travelers.clear();
-        self.queues.clear();
-        self.waiting_for_queue.clear();
-        self.output = None;
-        self.output_stream.clear();
+        self.sim.reset();
+        self.timeline.clear();
     }
 
     pub fn step(&mut self) {
-        if self.collision_free() {
-            self.step_travelers();
-        } else {
-            log::error!("Collision detected");
-        }
-    }
-    fn is_output_cell(&self, location: IVec2) -> bool {
-        location == self.dims.as_ivec2() - ivec2(1, 0)
-            || self.mode == MatrixMode::L3X && location == self.dims.as_ivec2() - ivec2(2, 0)
-    }
-
-    /// Iterates through the travelers stored in this matrix and checks whether they collide.
-    /// Ignores collisions on a queue *&&* between a traveler aligned and one not aligned with the
-    /// queue.
-    fn collision_free(&self) -> bool {
-        let mut collision_check = HashSet::new();
-        let mut queue_collision_check = HashMap::<_, Alignments>::new();
-        self.travelers.iter().all(|traveler| {
-            self.instructions
-                .get(&traveler.location)
-                .map(|l3x| {
-                    if l3x.command == L3XCommand::Queue {
-                        let aligned = Alignments::aligned(l3x.direction, traveler.direction);
-                        if let Some(alignments) = queue_collision_check.get_mut(&traveler.location)
-                        {
-                            if alignments.contains(aligned) {
-                                false
-                            } else {
-                                *alignments &= aligned;
-                                true
-                            }
-                        } else {
-                            queue_collision_check.insert(traveler.location, aligned);
-                            true
-                        }
-                    } else {
-                        collision_check.insert(traveler.location)
-                    }
-                })
-                .unwrap_or_else(|| self.is_output_cell(traveler.location))
-        })
-    }
-
-    fn step_travelers(&mut self) -> Result<(), ()> {
-        self.travelers.try_swap(|mut traveler| {
-            let instruction = if traveler.location.cmplt(self.dims.as_ivec2()).all() {
-                self.instructions.get(&traveler.location).ok_or(())?
-            } else if traveler.location == self.dims.as_ivec2() - ivec2(1, 0) {
-                return if self.output.is_none() {
-                    self.output = Some(traveler.value);
-                    Ok(smallvec![])
-                } else {
-                    Err(()) // will be a different type of error than out-of-bounds
-                };
-            } else if traveler.location == self.dims.as_ivec2() - ivec2(2, 0) {
-                self.output_stream.push(traveler.value);
-                return Ok(smallvec![]);
-            } else {
-                return Err(());
-            };
-
-            let aligned = traveler.direction == instruction.direction;
-
-            let out: SmallVec<[_; 2]> = match &instruction.command {
-                L3XCommand::Multiply(with) => {
-                    smallvec![if aligned {
-                        traveler.mul(with).direct(instruction.direction)
-                    } else if let Some(div) = traveler.value.try_div(with) {
-                        traveler.value(div).direct(instruction.direction)
-                    } else {
-                        traveler.direct(instruction.direction.opposite())
-                    }]
-                }
-                L3XCommand::Duplicate => {
-                    smallvec![
-                        traveler.clone().direct(instruction.direction),
-                        traveler.direct(instruction.direction.opposite())
-                    ]
-                }
-                L3XCommand::Queue => {
-                    if aligned {
-                        self.queues
-                            .entry(traveler.location)
-                            .and_modify(|q| q.push_back(traveler.value.clone()))
-                            .or_insert_with(|| vec![traveler.value.clone()].into());
-                        smallvec![]
-                    } else {
-                        traveler.direction = instruction.direction;
-                        if let Some(queued) = self
-                            .queues
-                            .get_mut(&traveler.location)
-                            .and_then(|q| q.pop_front())
-                        {
-                            smallvec![traveler.mul(&queued).step()]
-                        } else {
-                            self.waiting_for_queue.push((traveler, Registers::ONE));
-                            smallvec![]
-                        }
+        self.sim.checking_breakpoints = self.breakpoints.any_enabled();
+        match self.sim.step() {
+            Ok(()) => {
+                self.timeline.record(&self.sim);
+                if self.stepping {
+                    for traveler in self.sim.watched_travelers() {
+                        log::info!(
+                            "Watch point at {} reached with {}",
+                            traveler.location,
+                            traveler.value
+                        );
+                        self.stepping = false;
                     }
                 }
-                L3XCommand::Annihilate => {
-                    smallvec![traveler.value(Registers::ONE).direct(instruction.direction)]
+                for breakpoint in self.breakpoints.hit(&self.sim) {
+                    log::info!(
+                        "Breakpoint {} hit at tick {}",
+                        breakpoint.condition,
+                        self.sim.ticks()
+                    );
+                    self.stepping = false;
                 }
-            };
-            Ok(out)
-        })?;
-
-        let dequeued_travelers = self
-            .waiting_for_queue
-            .e_drain_where(|(traveler, u)| {
-                let queued_traveler = self
-                    .travelers
-                    .iter()
-                    .position(|e| e.location == traveler.location)
-                    .map(|ix| self.travelers.swap_remove(ix).value);
+            }
+            Err(e) => {
+                log::error!("Simulation stopped: {e}");
+                self.stepping = false;
+            }
+        }
+    }
 
-                queued_traveler.map(|register| *u = register).is_some()
-            })
-            .map(|(traveler, multiplier)| traveler.mul(&multiplier).step())
-            .collect_vec();
+    /// Steps as fast as possible until the program halts, raises an error, or hits a breakpoint
+    pub fn run_to_end(&mut self) {
+        self.running_to_end = true;
+        self.stepping = true;
+    }
 
-        self.travelers.extend(dequeued_travelers);
+    pub fn cancel_run_to_end(&mut self) {
+        if self.running_to_end && self.simulating {
+            self.timeline.record_now(&self.sim);
+        }
+        self.running_to_end = false;
+        self.stepping = false;
+    }
 
-        Ok(())
+    fn run_to_end_for_frame(&mut self) {
+        let start = get_time();
+        while self.stepping && !self.sim.is_halted() {
+            self.step();
+            if get_time() - start > RUN_TO_END_FRAME_BUDGET {
+                return;
+            }
+        }
+        if self.sim.is_halted() {
+            log::info!("Program halted after {} ticks", self.sim.ticks());
+        }
+        self.cancel_run_to_end();
     }
+
+    pub fn step_back(&mut self) {
+        if let Some(tick) = self.sim.ticks().checked_sub(1) {
+            self.seek(tick);
+        }
+    }
+
+    /// Rewinds (or fast-forwards) the simulation to a tick which has already been simulated
+    pub fn seek(&mut self, tick: usize) {
+        self.stepping = false;
+        let Some(snapshot) = self.timeline.seek(tick) else {
+            return;
+        };
+        self.sim.restore(snapshot);
+        // step the rest of the way from the snapshot, jumping over loops only as far as the tick
+        self.sim.skip_until = tick;
+        while self.sim.ticks() < tick && !self.sim.is_halted() && self.sim.step().is_ok() {}
+        self.sim.skip_until = usize::MAX;
+    }
+
     fn snip(&mut self, range: Selection) -> L3XData {
         let mut res = vec![];
+        let mut colors = vec![];
         for i in range.starts.y..range.ends.y + 1 {
             let mut row = vec![];
+            let mut color_row = vec![];
             for j in range.starts.x..range.ends.x + 1 {
                 row.push(MaybeL3X::from(
-                    self.instructions.remove(&IVec2::from((j, i))),
+                    self.sim.instructions.remove(&IVec2::from((j, i))),
                 ));
+                color_row.push(self.colors.remove(&IVec2::from((j, i))));
             }
             res.push(row);
+            colors.push(color_row);
         }
+        let inside = self
+            .comments
+            .keys()
+            .filter(|area| range.contains_area(area))
+            .copied()
+            .collect::<Vec<_>>();
+        let comments = inside
+            .into_iter()
+            .map(|area| {
+                let text = self.comments.remove(&area).unwrap_or_default();
+                (area.to(area.starts - range.starts), text)
+            })
+            .collect();
         L3XData {
             data: res,
+            colors,
+            comments,
             dims: UVec2::from((range.width() as u32, range.height() as u32)),
         }
     }
+
+    /// Copies the selected area without taking it out of the matrix
+    fn peek(&mut self, range: Selection) -> L3XData {
+        let data = self.snip(range);
+        self.apply_raw(Paste(range.starts, data.clone()));
+        data
+    }
     fn apply_raw(&mut self, a: MatrixAction) {
         match a {
             Resize(dims) => {
-                self.dims = dims;
+                self.sim.dims = dims;
             }
             Swap(selection, target) => {
+                let moved = selection.to(target);
+                self.remap_comments(|area| {
+                    if selection.contains_area(&area) {
+                        Some(area.to(area.starts - selection.starts + target))
+                    } else if moved.contains_area(&area) {
+                        Some(area.to(area.starts - target + selection.starts))
+                    } else {
+                        None
+                    }
+                });
                 {
                     for i in 0..selection.height() {
                         for j in 0..selection.width() {
@@ -597,6 +712,15 @@ impl Matrix {
                 };
             }
             ReflectH(selection) => {
+                self.remap_comments(|area| {
+                    selection.contains_area(&area).then(|| {
+                        let mirror = selection.starts.x + selection.ends.x;
+                        Selection {
+                            starts: ivec2(mirror - area.ends.x, area.starts.y),
+                            ends: ivec2(mirror - area.starts.x, area.ends.y),
+                        }
+                    })
+                });
                 {
                     for i in 0..selection.height() {
                         for j in 0..(selection.width() + 1) / 2 {
@@ -613,6 +737,15 @@ impl Matrix {
                 };
             }
             ReflectV(selection) => {
+                self.remap_comments(|area| {
+                    selection.contains_area(&area).then(|| {
+                        let mirror = selection.starts.y + selection.ends.y;
+                        Selection {
+                            starts: ivec2(area.starts.x, mirror - area.ends.y),
+                            ends: ivec2(area.ends.x, mirror - area.starts.y),
+                        }
+                    })
+                });
                 {
                     for i in 0..(selection.height() + 1) / 2 {
                         for j in 0..selection.width() {
@@ -629,6 +762,12 @@ impl Matrix {
                 };
             }
             Transpose(selection) => {
+                self.remap_comments(|area| {
+                    selection.contains_area(&area).then(|| Selection {
+                        starts: selection.starts + (area.starts - selection.starts).yx(),
+                        ends: selection.starts + (area.ends - selection.starts).yx(),
+                    })
+                });
                 {
                     let width = max(selection.width(), selection.height());
                     let height = min(selection.width(), selection.height());
@@ -652,15 +791,30 @@ impl Matrix {
                 for i in 0..data.dims.y {
                     for j in 0..data.dims.x {
                         Option::<L3X>::from(data[uvec2(j, i)].optionalTake()).map_or(
-                            self.instructions
+                            self.sim
+                                .instructions
                                 .remove(&(target + ivec2(j as i32, i as i32))),
                             |c| {
-                                self.instructions
+                                self.sim
+                                    .instructions
                                     .insert(target + ivec2(j as i32, i as i32), c)
                             },
                         );
+                        let location = target + ivec2(j as i32, i as i32);
+                        match data.colors[i as usize][j as usize] {
+                            Some(color) => self.colors.insert(location, color),
+                            None => self.colors.remove(&location),
+                        };
                     }
                 }
+                let region = Selection {
+                    starts: target,
+                    ends: target + data.dims.as_ivec2() - IVec2::ONE,
+                };
+                self.comments.retain(|area, _| !region.contains_area(area));
+                for (area, text) in data.comments {
+                    self.comments.insert(area.to(target + area.starts), text);
+                }
             }
         };
     }
@@ -674,10 +828,138 @@ impl Matrix {
         let t = self.history.pop();
         t.map_or((), |f| self.apply_raw(f))
     }
+    /// Moves each comment to where `f` puts its area, or leaves it where it is if `f` gives
+    /// `None`. Comments are only moved along with an edit when they lie entirely within the cells
+    /// it moves.
+    fn remap_comments(&mut self, f: impl Fn(Selection) -> Option<Selection>) {
+        self.comments = self
+            .comments
+            .drain()
+            .map(|(area, text)| (f(area).unwrap_or(area), text))
+            .collect();
+    }
+
     fn swap_and_map<F: Fn(L3X) -> L3X>(&mut self, k1: IVec2, k2: IVec2, f: F) {
-        let e1 = self.instructions.remove_entry(&k1);
-        let e2 = self.instructions.remove_entry(&k2);
-        e1.and_then(|(k, v)| self.instructions.insert(k2, f(v)));
-        e2.and_then(|(k, v)| self.instructions.insert(k1, f(v)));
+        let e1 = self.sim.instructions.remove_entry(&k1);
+        let e2 = self.sim.instructions.remove_entry(&k2);
+        e1.and_then(|(k, v)| self.sim.instructions.insert(k2, f(v)));
+        e2.and_then(|(k, v)| self.sim.instructions.insert(k1, f(v)));
+        let c1 = self.colors.remove(&k1);
+        let c2 = self.colors.remove(&k2);
+        c1.and_then(|c| self.colors.insert(k2, c));
+        c2.and_then(|c| self.colors.insert(k1, c));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_follow_edits() {
+        let mut matrix = Matrix::default();
+        matrix.import_data(b"1D,1L\n3R,2U\n", Some(MatrixMode::L3));
+        let top = Selection {
+            starts: ivec2(0, 0),
+            ends: ivec2(1, 0),
+        };
+
+        let mut data = matrix.peek(top);
+        data.colors = vec![vec![Some(CellColor::Red); 2]];
+        matrix.apply(Paste(top.starts, data));
+        assert_eq!(matrix.colors.len(), 2);
+        assert_eq!(matrix.sim.instructions.len(), 4, "painting keeps the instructions");
+
+        let copied = matrix.peek(top);
+        matrix.apply(Paste(ivec2(0, 1), copied));
+        assert_eq!(matrix.colors.get(&ivec2(1, 1)), Some(&CellColor::Red));
+        matrix.undo();
+        assert_eq!(matrix.colors.get(&ivec2(1, 1)), None);
+
+        matrix.apply(ReflectV(Selection {
+            starts: ivec2(0, 0),
+            ends: ivec2(0, 1),
+        }));
+        assert_eq!(matrix.colors.get(&ivec2(0, 1)), Some(&CellColor::Red));
+        matrix.undo();
+        matrix.undo();
+        assert!(matrix.colors.is_empty());
+    }
+
+    #[test]
+    fn comments_follow_edits() {
+        let mut matrix = Matrix::default();
+        matrix.import_data(b"1D,1L,\n3R,2U,\n", Some(MatrixMode::L3));
+        let text = |matrix: &Matrix, area: Selection| matrix.comments.get(&area).cloned();
+        let cell = Selection::from(ivec2(0, 0));
+        matrix.comments.insert(cell, "start".to_string());
+        let row = Selection {
+            starts: ivec2(0, 0),
+            ends: ivec2(2, 0),
+        };
+        matrix.comments.insert(row, "top row".to_string());
+
+        matrix.apply(ReflectH(row));
+        assert_eq!(text(&matrix, ivec2(2, 0).into()).as_deref(), Some("start"));
+        assert_eq!(text(&matrix, row).as_deref(), Some("top row"));
+        matrix.undo();
+        assert_eq!(text(&matrix, cell).as_deref(), Some("start"));
+
+        let copied = matrix.peek(Selection {
+            starts: ivec2(0, 0),
+            ends: ivec2(1, 0),
+        });
+        assert_eq!(text(&matrix, cell).as_deref(), Some("start"), "copying keeps it");
+        matrix.apply(Paste(ivec2(1, 1), copied));
+        assert_eq!(text(&matrix, ivec2(1, 1).into()).as_deref(), Some("start"));
+        matrix.undo();
+        assert_eq!(text(&matrix, ivec2(1, 1).into()), None);
+
+        matrix.apply(Swap(cell, ivec2(2, 1)));
+        assert_eq!(text(&matrix, ivec2(2, 1).into()).as_deref(), Some("start"));
+        matrix.undo();
+        matrix.apply(Transpose(Selection {
+            starts: ivec2(0, 0),
+            ends: ivec2(1, 1),
+        }));
+        assert_eq!(matrix.comments.len(), 2);
+        assert_eq!(text(&matrix, cell).as_deref(), Some("start"));
+    }
+
+    #[test]
+    fn seek_between_snapshots() {
+        let mut matrix = Matrix::default();
+        matrix.import_data(b"1D,1L\n1D,3U\n1R,2U\n", Some(MatrixMode::L3));
+        matrix
+            .single_input
+            .set_value(crate::registers::Registers::from(vec![(2, 50)]));
+        matrix.sim.skip_loops = false;
+        matrix.init_simulation();
+        for _ in 0..100 {
+            matrix.step();
+        }
+        matrix.seek(50);
+        assert_eq!(matrix.sim.ticks(), 50);
+        matrix.step_back();
+        assert_eq!(matrix.sim.ticks(), 49);
+        assert_eq!(matrix.timeline.range(), Some((0, 100)));
+        matrix.step();
+        assert_eq!(matrix.timeline.range(), Some((0, 50)));
+
+        matrix.sim.skip_loops = true;
+        matrix.run_to_end();
+        while !matrix.sim.is_halted() {
+            matrix.step();
+        }
+        matrix.cancel_run_to_end();
+        assert!(matrix.sim.is_halted());
+        let end = matrix.sim.ticks();
+        assert_eq!(matrix.timeline.range(), Some((0, end)));
+        matrix.step_back();
+        assert_eq!(matrix.sim.ticks(), end - 1);
+        matrix.seek(end - 10);
+        assert_eq!(matrix.sim.ticks(), end - 10);
+        matrix.seek(end);
+        assert_eq!(matrix.sim.ticks(), end);
     }
 }
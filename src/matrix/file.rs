@@ -1,91 +1,355 @@
-use csv::{ReaderBuilder, WriterBuilder};
+use csv::{ReaderBuilder, Terminator, WriterBuilder};
 use macroquad::prelude::*;
 use ndarray::{ArrayBase, OwnedRepr};
 use ndarray_csv::{Array2Reader, Array2Writer};
 
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, fmt::Display};
 
 use crate::{
-    l3x::{L3XParseError, MaybeL3X},
-    wasync::AsyncContext,
+    l3x::{CellStyle, L3XCommand, L3XParseError, MaybeL3X, L3X},
+    wasync::{AsyncContext, ImportKind},
 };
 
 use super::{Matrix, MatrixMode};
 
 impl Matrix {
     pub fn try_import_data(&mut self, ctx: &mut AsyncContext) {
-        if let Some((data, ext)) = ctx.try_open_file() {
-            self.import_data(&data, ext)
+        match ctx.try_open_file() {
+            Some((data, ImportKind::Program(mode))) => self.import_data(&data, mode),
+            Some((data, ImportKind::Project)) => {
+                if let Err(e) = self.import_project(&data) {
+                    log::error!("Failed to import project: {e}");
+                }
+            }
+            None => (),
         }
     }
 }
 
-impl Matrix {
-    pub(super) fn export_data(&self) -> Result<Vec<u8>, csv::Error> {
-        log::debug!("Beginning file export");
-        let mut arr = ArrayBase::<OwnedRepr<_>, _>::from_elem(
-            [self.dims.y as usize, self.dims.x as usize],
-            Cow::Borrowed(""),
-        );
+/// Writes a program out as csv. Cells which are unchanged since the program was read from `source`
+/// keep their original spelling, as do cells which could not be read, while every other cell is
+/// spelled in the given style. If the program is still the size it was when it was read, any extra
+/// rows and columns of the original file are kept as well.
+pub fn write_program(
+    instructions: &HashMap<IVec2, L3X>,
+    dims: UVec2,
+    source: Option<&SourceLayout>,
+    style: CellStyle,
+) -> Result<Vec<u8>, csv::Error> {
+    let file_dims = match source {
+        Some(source) if source.program_dims == dims => source.file_dims,
+        _ => dims,
+    };
 
-        for (loc @ &IVec2 { x, y }, item) in &self.instructions {
-            if loc.cmplt(self.dims.as_ivec2()).all() {
-                arr[(y as usize, x as usize)] = Cow::Owned(item.to_string());
-            }
-        }
+    let mut arr = ArrayBase::<OwnedRepr<_>, _>::from_elem(
+        [file_dims.y as usize, file_dims.x as usize],
+        Cow::Borrowed(""),
+    );
+    for ((y, x), cell) in arr.indexed_iter_mut() {
+        let location = ivec2(x as i32, y as i32);
+        let instruction = instructions
+            .get(&location)
+            .filter(|_| location.cmplt(dims.as_ivec2()).all());
+        let original = source.and_then(|source| source.cells.get(&location));
+        *cell = match (instruction, original) {
+            (Some(l3x), Some((text, Some(read)))) if read == l3x => Cow::Borrowed(text.as_str()),
+            (Some(l3x), _) => Cow::Owned(l3x.to_styled_string(style)),
+            // the instruction has since been deleted
+            (None, Some((_, Some(_)))) | (None, None) => Cow::Borrowed(""),
+            (None, Some((text, None))) => Cow::Borrowed(text.as_str()),
+        };
+    }
 
-        let mut buf_out = Vec::new();
-        let mut writer = WriterBuilder::new()
-            .has_headers(false)
-            .from_writer(&mut buf_out);
-        if let Err(e) = writer.serialize_array2(&arr) {
-            Err(e)
+    let crlf = source.is_some_and(|source| source.crlf);
+    let mut buf_out = Vec::new();
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .terminator(if crlf {
+            Terminator::CRLF
         } else {
-            drop(writer);
-            Ok(buf_out)
-        }
+            Terminator::Any(b'\n')
+        })
+        .from_writer(&mut buf_out);
+    writer.serialize_array2(&arr)?;
+    drop(writer);
+    if source.is_some_and(|source| !source.trailing_newline) {
+        buf_out.truncate(buf_out.len().saturating_sub(if crlf { 2 } else { 1 }));
+    }
+    Ok(buf_out)
+}
+
+impl Matrix {
+    pub(super) fn export_data(&self) -> Result<Vec<u8>, csv::Error> {
+        log::debug!("Beginning file export");
+        write_program(
+            &self.sim.instructions,
+            self.sim.dims,
+            self.source.as_ref(),
+            self.export_style,
+        )
     }
 
-    fn import_data(&mut self, data: &[u8], extension: Option<MatrixMode>) {
-        let mut reader = ReaderBuilder::new().has_headers(false).from_reader(data);
-        let array = match reader.deserialize_array2_dynamic::<String>() {
-            Ok(a) => a,
+    pub(super) fn import_data(&mut self, data: &[u8], extension: Option<MatrixMode>) {
+        let mut program = match read_program(data, extension.unwrap_or(MatrixMode::L3)) {
+            Ok(program) => program,
             Err(e) => {
                 log::error!("Failed to deserialize file: {e:?}");
                 return;
             }
         };
 
-        if array.is_empty() {
+        if program.dims == UVec2::ZERO {
             log::warn!("Imported file was empty, doing nothing");
             return;
         }
 
-        let mut max_loc = IVec2::ZERO;
-        let mut instruction_buffer = HashMap::new();
-        if let Err(err) = array
-            .columns()
-            .into_iter()
-            .enumerate()
-            .flat_map(|(x, col)| {
-                col.into_iter()
-                    .enumerate()
-                    .map(move |(y, elem)| (ivec2(x as i32, y as i32), elem))
-            })
-            .try_for_each(|(loc, elem)| {
-                log::trace!("trying cell: {elem} at {loc}");
-                if let MaybeL3X::Some(l3x) = MaybeL3X::try_from(elem.as_str())? {
-                    max_loc = max_loc.max(loc);
-                    instruction_buffer.insert(loc, l3x);
-                }
-                Result::<_, L3XParseError>::Ok(())
-            })
-        {
-            log::error!("inport failure: {err:?}");
+        if program.issues.is_empty() {
+            self.import_report = None;
+            self.load_program(program);
+        } else {
+            // let the user decide whether the valid cells are worth keeping
+            self.import_report = Some(ImportReport {
+                issues: std::mem::take(&mut program.issues),
+                pending: Some(program),
+            });
+        }
+    }
+
+    pub(super) fn load_program(&mut self, program: ProgramFile) {
+        // a simulation of the old program has nothing to do with the new one
+        self.cancel_run_to_end();
+        self.cleanup_simulation();
+        self.sim.instructions = program.instructions;
+        self.sim.dims = program.dims;
+        self.sim.mode = program.mode;
+        self.source = Some(program.source);
+        // comments and colors on the old program would only be in the way
+        self.comments.clear();
+        self.colors.clear();
+    }
+}
+
+/// What went wrong with a single cell of an imported program
+#[derive(Debug, PartialEq)]
+pub enum CellProblem {
+    Parse(L3XParseError),
+    /// The command is only part of L3X, but the program is being read as L3
+    L3XOnly,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ImportIssue {
+    pub location: IVec2,
+    pub text: String,
+    pub problem: CellProblem,
+}
+
+impl Display for ImportIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = cell_name(self.location);
+        match self.problem {
+            CellProblem::Parse(ref e) => write!(f, "{name}: {e:?} '{}'", self.text),
+            CellProblem::L3XOnly => write!(f, "{name}: '{}' is only allowed in L3X", self.text),
+        }
+    }
+}
+
+/// The name a spreadsheet would give to a cell, such as `C7` for the third column of the seventh
+/// row
+pub fn cell_name(location: IVec2) -> String {
+    let mut column = String::new();
+    let mut x = location.x + 1;
+    while x > 0 {
+        let digit = (x - 1) % 26;
+        column.insert(0, (b'A' + digit as u8) as char);
+        x = (x - 1) / 26;
+    }
+    format!("{column}{}", location.y + 1)
+}
+
+/// A program read from a file, along with every cell which could not be read
+pub struct ProgramFile {
+    pub instructions: HashMap<IVec2, L3X>,
+    /// Covers every nonempty cell of the file, including the ones which could not be read
+    pub dims: UVec2,
+    pub mode: MatrixMode,
+    pub issues: Vec<ImportIssue>,
+    pub source: SourceLayout,
+}
+
+/// How a program was laid out in the file it was read from, so that it can be written back out
+/// without disturbing the parts which have not changed
+pub struct SourceLayout {
+    /// The text of every nonempty cell, along with the instruction it was read as, if any
+    pub cells: HashMap<IVec2, (String, Option<L3X>)>,
+    /// The size of the file, including any empty rows and columns at the end
+    pub file_dims: UVec2,
+    /// The size of the program when it was read
+    pub program_dims: UVec2,
+    pub crlf: bool,
+    pub trailing_newline: bool,
+}
+
+/// An import which had problems, kept around so that they can be looked at
+pub struct ImportReport {
+    pub issues: Vec<ImportIssue>,
+    /// The cells which could be read, until it is decided whether to import them
+    pub pending: Option<ProgramFile>,
+}
+
+/// Reads a csv-formatted program, keeping every cell which can be read and listing the rest in
+/// spreadsheet order
+pub fn read_program(data: &[u8], mode: MatrixMode) -> Result<ProgramFile, csv::Error> {
+    let mut max_loc = IVec2::NEG_ONE;
+    let mut instructions = HashMap::new();
+    let mut issues = vec![];
+    let (cells, file_dims) = parse_cells(data)?;
+    let mut source_cells = HashMap::new();
+    for (location, text, cell) in cells {
+        if !text.is_empty() {
+            let read = match cell {
+                Ok(MaybeL3X::Some(ref l3x)) => Some(l3x.clone()),
+                _ => None,
+            };
+            source_cells.insert(location, (text.clone(), read));
         }
+        let problem = match cell {
+            Ok(MaybeL3X::None) => continue,
+            Ok(MaybeL3X::Some(l3x)) => {
+                if mode == MatrixMode::L3 && !matches!(l3x.command, L3XCommand::Multiply(_)) {
+                    source_cells.insert(location, (text.clone(), None));
+                    CellProblem::L3XOnly
+                } else {
+                    max_loc = max_loc.max(location);
+                    instructions.insert(location, l3x);
+                    continue;
+                }
+            }
+            Err(e) => CellProblem::Parse(e),
+        };
+        max_loc = max_loc.max(location);
+        issues.push(ImportIssue {
+            location,
+            text,
+            problem,
+        });
+    }
+    issues.sort_by_key(|issue| (issue.location.y, issue.location.x));
+
+    let dims = (max_loc + IVec2::ONE).as_uvec2();
+    Ok(ProgramFile {
+        instructions,
+        dims,
+        mode,
+        issues,
+        source: SourceLayout {
+            cells: source_cells,
+            file_dims,
+            program_dims: dims,
+            crlf: data.windows(2).any(|pair| pair == b"\r\n"),
+            trailing_newline: data.ends_with(b"\n"),
+        },
+    })
+}
+
+type ParsedCell = (IVec2, String, Result<MaybeL3X, L3XParseError>);
+
+/// Reads a csv-formatted program and parses each of its cells, column by column. Failing to parse
+/// a cell does not stop the rest of the cells from being parsed, so it is up to the caller to
+/// decide what to do with the bad ones. Also returns the size of the file.
+fn parse_cells(data: &[u8]) -> Result<(Vec<ParsedCell>, UVec2), csv::Error> {
+    let mut reader = ReaderBuilder::new().has_headers(false).from_reader(data);
+    let array = reader.deserialize_array2_dynamic::<String>()?;
+    let (rows, columns) = array.dim();
+
+    let cells = array
+        .columns()
+        .into_iter()
+        .enumerate()
+        .flat_map(|(x, col)| {
+            col.into_iter()
+                .enumerate()
+                .map(move |(y, elem)| (ivec2(x as i32, y as i32), elem))
+        })
+        .map(|(loc, elem)| {
+            log::trace!("trying cell: {elem} at {loc}");
+            let cell = MaybeL3X::try_from(elem.as_str());
+            (loc, elem.clone(), cell)
+        })
+        .collect();
+    Ok((cells, uvec2(columns as u32, rows as u32)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::l3x::DirectionLetters;
+
+    #[test]
+    fn cell_names() {
+        assert_eq!(cell_name(ivec2(0, 0)), "A1");
+        assert_eq!(cell_name(ivec2(2, 6)), "C7");
+        assert_eq!(cell_name(ivec2(25, 9)), "Z10");
+        assert_eq!(cell_name(ivec2(26, 0)), "AA1");
+        assert_eq!(cell_name(ivec2(701, 0)), "ZZ1");
+        assert_eq!(cell_name(ivec2(702, 0)), "AAA1");
+    }
+
+    #[test]
+    fn import_issues() {
+        let data = b"1D,%S,\n1R,3x,\n,,0D\n";
+        let program = read_program(data, MatrixMode::L3).unwrap();
+        assert_eq!(program.dims, uvec2(3, 3));
+        assert_eq!(program.instructions.len(), 2);
+        assert_eq!(
+            program
+                .issues
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "B1: '%S' is only allowed in L3X",
+                "B2: BadDirection '3x'",
+                "C3: ZeroCommand '0D'",
+            ]
+        );
+
+        let program = read_program(data, MatrixMode::L3X).unwrap();
+        assert_eq!(program.instructions.len(), 3);
+        assert_eq!(program.issues.len(), 2);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"3n,1r;,oops,\r\n2s,,,\r\n,,,\r\n";
+        let program = read_program(data, MatrixMode::L3).unwrap();
+        let style = CellStyle {
+            letters: DirectionLetters::Nsew,
+            lowercase: false,
+        };
+        let written = |instructions: &HashMap<IVec2, L3X>, dims| {
+            let out = write_program(instructions, dims, Some(&program.source), style).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            written(&program.instructions, program.dims),
+            std::str::from_utf8(data).unwrap()
+        );
+
+        let mut instructions = program.instructions.clone();
+        instructions.insert(ivec2(0, 0), L3X::try_from("5D").unwrap());
+        instructions.remove(&ivec2(1, 0));
+        assert_eq!(
+            written(&instructions, program.dims),
+            "5S,,oops,\r\n2s,,,\r\n,,,\r\n"
+        );
+        assert_eq!(
+            written(&instructions, uvec2(2, 2)),
+            "5S,\r\n2s,\r\n",
+            "resizing the program drops the rest of the file"
+        );
 
-        self.instructions = instruction_buffer;
-        self.dims = (max_loc + IVec2::ONE).as_uvec2();
-        self.mode = extension.unwrap_or(MatrixMode::L3)
+        let out = write_program(&instructions, uvec2(1, 2), None, CellStyle::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "5D\n2D\n");
     }
 }
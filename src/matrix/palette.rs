@@ -0,0 +1,68 @@
+use macroquad::prelude::Color;
+use serde::{Deserialize, Serialize};
+
+/// A background color which can be given to cells to show how a program is organized. Colors are
+/// only kept in project files, since a bare csv program has nowhere to put them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellColor {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Teal,
+    Blue,
+    Purple,
+    Gray,
+}
+
+impl CellColor {
+    pub const ALL: [CellColor; 8] = [
+        CellColor::Red,
+        CellColor::Orange,
+        CellColor::Yellow,
+        CellColor::Green,
+        CellColor::Teal,
+        CellColor::Blue,
+        CellColor::Purple,
+        CellColor::Gray,
+    ];
+
+    /// The color as drawn under a cell, light enough that the arrows and text stay readable
+    pub fn color(self) -> Color {
+        let (r, g, b) = self.rgb();
+        Color::from_rgba(r, g, b, 110)
+    }
+
+    /// The color as shown in the palette
+    pub fn swatch(self) -> egui::Color32 {
+        let (r, g, b) = self.rgb();
+        egui::Color32::from_rgb(r, g, b)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CellColor::Red => "red",
+            CellColor::Orange => "orange",
+            CellColor::Yellow => "yellow",
+            CellColor::Green => "green",
+            CellColor::Teal => "teal",
+            CellColor::Blue => "blue",
+            CellColor::Purple => "purple",
+            CellColor::Gray => "gray",
+        }
+    }
+
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            CellColor::Red => (230, 80, 70),
+            CellColor::Orange => (240, 150, 50),
+            CellColor::Yellow => (235, 210, 60),
+            CellColor::Green => (90, 180, 80),
+            CellColor::Teal => (60, 170, 170),
+            CellColor::Blue => (70, 120, 220),
+            CellColor::Purple => (150, 90, 200),
+            CellColor::Gray => (130, 130, 130),
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use glam::{ivec2, IVec2};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    registers::{DisplayMode, RegisterParseError, Registers},
+    ruleset::Ruleset,
+    simulator::{MatrixMode, QueueSemantics},
+    testcase::TestCase,
+};
+
+use super::{
+    file::{read_program, write_program, ImportIssue},
+    palette::CellColor,
+    Matrix, Selection,
+};
+
+/// The version of the project format written by this build. Bump it whenever the format changes,
+/// and teach [Project::from_json] to upgrade projects written by older versions.
+pub const PROJECT_VERSION: u32 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProjectError {
+    #[error("Could not read the project: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("The project was saved by a newer version of the IDE (format version {0}, but only up to {PROJECT_VERSION} can be read)")]
+    TooNew(u32),
+    #[error("Could not read the program of the project: {0}")]
+    Program(#[from] csv::Error),
+    #[error("Could not read a value of the project: {0}")]
+    Value(#[from] RegisterParseError),
+    #[error("Some cells of the program could not be read: {}", .0.iter().join(", "))]
+    Cells(Vec<ImportIssue>),
+}
+
+/// A program together with everything else about it worth keeping between sessions, stored as
+/// JSON in `.l3xproj` files. Values are written as products of prime powers, so that huge ones
+/// can be read back without factoring them.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Project {
+    pub version: u32,
+    pub mode: MatrixMode,
+    /// The grid, in the same form as a bare csv program
+    pub program: String,
+    #[serde(default)]
+    pub ruleset: Ruleset,
+    #[serde(default)]
+    pub queue_semantics: QueueSemantics,
+    #[serde(default)]
+    pub input: String,
+    #[serde(default)]
+    pub stream: Vec<String>,
+    #[serde(default)]
+    pub tests: Vec<ProjectTest>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub comments: Vec<ProjectComment>,
+    #[serde(default)]
+    pub colors: Vec<ProjectColor>,
+    #[serde(default)]
+    pub view: ViewSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ProjectTest {
+    pub input: String,
+    #[serde(default)]
+    pub stream: Vec<String>,
+    pub expected_output: String,
+    #[serde(default)]
+    pub expected_stream: Vec<String>,
+}
+
+/// A comment on the rectangle of cells with its top left corner at `x`, `y`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ProjectComment {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub text: String,
+}
+
+impl From<(&Selection, &String)> for ProjectComment {
+    fn from((area, text): (&Selection, &String)) -> Self {
+        Self {
+            x: area.starts.x,
+            y: area.starts.y,
+            width: area.width(),
+            height: area.height(),
+            text: text.clone(),
+        }
+    }
+}
+
+impl ProjectComment {
+    fn area(&self) -> Selection {
+        let starts = ivec2(self.x, self.y);
+        Selection {
+            starts,
+            ends: starts + ivec2(self.width, self.height).max(IVec2::ONE) - IVec2::ONE,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ProjectColor {
+    pub x: i32,
+    pub y: i32,
+    pub color: CellColor,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(default)]
+pub struct ViewSettings {
+    pub gridlines: bool,
+    pub heatmap: bool,
+    pub display_mode: DisplayMode,
+    /// How many frames pass between ticks of the simulation
+    pub period: usize,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self {
+            gridlines: false,
+            heatmap: false,
+            display_mode: DisplayMode::Decimal,
+            period: 10,
+        }
+    }
+}
+
+/// Only the version of a project, read before the rest so that old formats can be told apart
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+fn value_text(value: &Registers) -> String {
+    value.display(DisplayMode::Factored).to_string()
+}
+
+fn stream_text(stream: &[Registers]) -> Vec<String> {
+    stream.iter().map(value_text).collect()
+}
+
+fn parse_stream(stream: &[String]) -> Result<Vec<Registers>, RegisterParseError> {
+    stream.iter().map(|value| value.parse()).try_collect()
+}
+
+impl From<&TestCase> for ProjectTest {
+    fn from(case: &TestCase) -> Self {
+        Self {
+            input: value_text(&case.input),
+            stream: stream_text(&case.stream),
+            expected_output: value_text(&case.expected_output),
+            expected_stream: stream_text(&case.expected_stream),
+        }
+    }
+}
+
+impl TryFrom<&ProjectTest> for TestCase {
+    type Error = RegisterParseError;
+
+    fn try_from(test: &ProjectTest) -> Result<Self, Self::Error> {
+        Ok(TestCase {
+            input: test.input.parse()?,
+            stream: parse_stream(&test.stream)?,
+            expected_output: test.expected_output.parse()?,
+            expected_stream: parse_stream(&test.expected_stream)?,
+        })
+    }
+}
+
+impl Project {
+    pub fn from_json(data: &[u8]) -> Result<Self, ProjectError> {
+        let Versioned { version } = serde_json::from_slice(data)?;
+        if version > PROJECT_VERSION {
+            return Err(ProjectError::TooNew(version));
+        }
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec_pretty(self)
+    }
+}
+
+impl Matrix {
+    pub(super) fn export_project(&self) -> Result<Vec<u8>, ProjectError> {
+        let program = write_program(
+            &self.sim.instructions,
+            self.sim.dims,
+            self.source.as_ref(),
+            self.export_style,
+        )?;
+        let project = Project {
+            version: PROJECT_VERSION,
+            mode: self.sim.mode,
+            // the csv writer only ever writes what it was given, which is all text
+            program: String::from_utf8_lossy(&program).into_owned(),
+            ruleset: self.sim.ruleset,
+            queue_semantics: self.sim.queue_semantics,
+            input: value_text(self.single_input.value()),
+            stream: stream_text(self.stream_input.value()),
+            tests: self
+                .test_suite
+                .cases
+                .iter()
+                .map(|(case, _)| ProjectTest::from(case))
+                .collect(),
+            notes: self.notes.clone(),
+            comments: self
+                .comments
+                .iter()
+                .map(ProjectComment::from)
+                .sorted_by_key(|comment| (comment.y, comment.x))
+                .collect(),
+            colors: self
+                .colors
+                .iter()
+                .map(|(location, &color)| ProjectColor {
+                    x: location.x,
+                    y: location.y,
+                    color,
+                })
+                .sorted_by_key(|color| (color.y, color.x))
+                .collect(),
+            view: ViewSettings {
+                gridlines: self.gridlines,
+                heatmap: self.heatmap,
+                display_mode: self.display_mode,
+                period: self.period,
+            },
+        };
+        Ok(project.to_json()?)
+    }
+
+    /// Replaces the program and everything stored alongside it. Nothing is changed unless the
+    /// whole project can be read.
+    pub(super) fn import_project(&mut self, data: &[u8]) -> Result<(), ProjectError> {
+        let project = Project::from_json(data)?;
+        let mut program = read_program(project.program.as_bytes(), project.mode)?;
+        if !program.issues.is_empty() {
+            return Err(ProjectError::Cells(program.issues));
+        }
+        program.dims = program.dims.max(project.mode.minimum_size());
+        let input = project.input.trim();
+        let input = if input.is_empty() {
+            Registers::default()
+        } else {
+            input.parse()?
+        };
+        let stream = parse_stream(&project.stream)?;
+        let tests: Vec<_> = project.tests.iter().map(TestCase::try_from).try_collect()?;
+
+        self.import_report = None;
+        self.load_program(program);
+        self.sim.ruleset = project.ruleset;
+        self.sim.queue_semantics = project.queue_semantics;
+        self.single_input.set_value(input);
+        self.stream_input.value = stream;
+        self.test_suite.set_cases(tests);
+        self.notes = project.notes;
+        self.comments = project
+            .comments
+            .into_iter()
+            .filter(|comment| !comment.text.is_empty())
+            .map(|comment| (comment.area(), comment.text))
+            .collect();
+        self.colors = project
+            .colors
+            .into_iter()
+            .map(|color| (ivec2(color.x, color.y), color.color))
+            .collect();
+        self.gridlines = project.view.gridlines;
+        self.heatmap = project.view.heatmap;
+        self.display_mode = project.view.display_mode;
+        self.period = project.view.period.max(1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_round_trip() {
+        let mut matrix = Matrix::default();
+        matrix.import_data(b"2D,\n5R,3U\n", Some(MatrixMode::L3));
+        matrix.single_input.set_value("2^1000·3".parse().unwrap());
+        matrix.test_suite.cases = vec![(
+            TestCase {
+                input: "4".parse().unwrap(),
+                stream: vec![],
+                expected_output: "9".parse().unwrap(),
+                expected_stream: vec![],
+            },
+            None,
+        )];
+        matrix.notes = "squares the power of 2".to_string();
+        let area = Selection {
+            starts: ivec2(0, 0),
+            ends: ivec2(1, 1),
+        };
+        matrix.comments.insert(area, "the whole loop".to_string());
+        matrix
+            .comments
+            .insert(ivec2(1, 1).into(), "decrement counter here".to_string());
+        matrix.colors.insert(ivec2(0, 1), CellColor::Teal);
+        matrix.heatmap = true;
+
+        let data = matrix.export_project().unwrap();
+        let project = Project::from_json(&data).unwrap();
+        assert_eq!(project.version, PROJECT_VERSION);
+        assert_eq!(project.input, "2^1000·3");
+
+        let mut loaded = Matrix::default();
+        loaded.import_project(&data).unwrap();
+        assert_eq!(loaded.sim.instructions, matrix.sim.instructions);
+        assert_eq!(loaded.sim.dims, matrix.sim.dims);
+        assert_eq!(loaded.single_input.value(), matrix.single_input.value());
+        assert_eq!(loaded.test_suite.cases, matrix.test_suite.cases);
+        assert_eq!(loaded.notes, matrix.notes);
+        assert_eq!(loaded.comments, matrix.comments);
+        assert_eq!(loaded.colors, matrix.colors);
+        assert_eq!(
+            project.comments[0],
+            ProjectComment {
+                x: 0,
+                y: 0,
+                width: 2,
+                height: 2,
+                text: "the whole loop".to_string(),
+            }
+        );
+        assert!(loaded.heatmap);
+        assert_eq!(loaded.export_data().unwrap(), b"2D,\n5R,3U\n");
+    }
+
+    #[test]
+    fn project_versions() {
+        let minimal = br#"{"version": 1, "mode": "l3x", "program": "1D"}"#;
+        let project = Project::from_json(minimal).unwrap();
+        assert_eq!(project.mode, MatrixMode::L3X);
+        assert_eq!(project.view, ViewSettings::default());
+
+        let newer = br#"{"version": 2, "mode": "l3", "program": "", "hologram": true}"#;
+        assert!(matches!(
+            Project::from_json(newer),
+            Err(ProjectError::TooNew(2))
+        ));
+        assert!(matches!(
+            Project::from_json(br#"{"mode": "l3"}"#),
+            Err(ProjectError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn project_import_is_all_or_nothing() {
+        let mut matrix = Matrix::default();
+        matrix.import_data(b"2D,\n5R,3U\n", Some(MatrixMode::L3));
+        let data = matrix.export_project().unwrap();
+        matrix.init_simulation();
+        matrix.step();
+        assert!(matrix.simulating);
+
+        let broken = br#"{"version": 1, "mode": "l3", "program": "2D,7Q\n"}"#;
+        match matrix.import_project(broken) {
+            Err(ProjectError::Cells(issues)) => assert_eq!(issues.len(), 1),
+            other => panic!("expected the bad cell to be rejected, got {other:?}"),
+        }
+        assert!(matrix.simulating, "nothing changes when the import fails");
+
+        matrix.import_project(&data).unwrap();
+        assert!(!matrix.simulating);
+        assert_eq!(matrix.sim.ticks(), 0);
+    }
+}
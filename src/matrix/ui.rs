@@ -1,14 +1,28 @@
 use crate::{
-    l3x::{L3XCommand, L3X, MaybeL3X},
-    registers::Registers,
+    batch::{parse_inputs, BatchRun, Outcome},
+    breakpoint::{Breakpoint, Condition},
+    factorize::Factoring,
+    l3x::{DirectionLetters, L3XCommand, L3X, MaybeL3X},
+    registers::{DisplayMode, RegisterParseError, Registers},
+    ruleset::Ruleset,
+    simulator::{QueueSemantics, Simulator},
+    symbolic::{Exploring, PathOutcome, PathResult, SymbolicValue},
+    testcase::{parse_case, parse_stream, start_run, TestCase, TestResult, Verdict, MAX_TICKS},
+    trace::TraceFormat,
     traveler::Traveler,
-    wasync::AsyncContext,
+    wasync::{AsyncContext, ExportKind},
 };
 use egui::{CollapsingHeader, CollapsingResponse, Ui, WidgetText};
+use itertools::Itertools;
 use macroquad::prelude::*;
 use vec_drain_where::VecDrainWhereExt;
 
-use super::{Matrix, MatrixMode,MatrixAction};
+use super::{palette::CellColor, Matrix, MatrixMode,MatrixAction, Selection};
+
+/// How long each frame may spend on work started from the panels, such as factoring a value or
+/// running a batch on web builds, in seconds. Kept well under a frame, since the panels stay in
+/// use while the work goes on.
+const FRAME_WORK_BUDGET: f64 = 0.01;
 
 trait EguiExt {
     fn collapsing_open<R>(
@@ -34,13 +48,42 @@ impl EguiExt for Ui {
 pub struct UiSingleInput {
     text: String,
     error_text: Option<String>,
+    factoring: Option<Factoring>,
     value: Registers,
 }
 
+/// Shows how far along a factorization is, with a button to cancel it. Returns its result once
+/// it is done.
+fn ui_factoring(
+    ui: &mut Ui,
+    factoring: &mut Option<Factoring>,
+) -> Option<Result<Vec<Registers>, RegisterParseError>> {
+    let running = factoring.as_mut()?;
+    let start = get_time();
+    if let Some(result) = running.poll(|| get_time() - start < FRAME_WORK_BUDGET) {
+        let result = result.clone();
+        *factoring = None;
+        return Some(result);
+    }
+    let remaining_digits = running.remaining_digits();
+    let progress = match running.progress() {
+        (_, 1) => String::new(),
+        (factored, total) => format!(" {factored} of {total} values done,"),
+    };
+    ui.horizontal(|ui| {
+        ui.spinner();
+        ui.label(format!("Factoring...{progress} {remaining_digits} digits left"));
+        if ui.button("Cancel").clicked() {
+            *factoring = None;
+        }
+    });
+    None
+}
+
 impl UiSingleInput {
-    fn ui(&mut self, ui: &mut Ui, simulating: bool) {
+    fn ui(&mut self, ui: &mut Ui, simulating: bool, mode: DisplayMode) {
         ui.set_enabled(!simulating);
-        ui.label(format!("Current value: {}", self.value));
+        ui.label(format!("Current value: {}", self.value.display(mode)));
         let text_edit = ui.text_edit_singleline(&mut self.text);
         if let Some(ref err) = self.error_text {
             ui.label(WidgetText::from(err).color(egui::Color32::RED));
@@ -49,30 +92,44 @@ impl UiSingleInput {
             self.error_text = None;
         }
         if text_edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-            match self.text.parse() {
-                Ok(registers) => self.value = registers,
+            match Factoring::parse(&self.text) {
+                Ok(factoring) => self.factoring = Some(factoring),
                 Err(e) => self.error_text = Some(e.to_string()),
             }
         }
+        match ui_factoring(ui, &mut self.factoring) {
+            Some(Ok(mut values)) => self.value = values.pop().unwrap_or_default(),
+            Some(Err(e)) => self.error_text = Some(e.to_string()),
+            None => (),
+        }
     }
 
     pub fn value(&self) -> &Registers {
         &self.value
     }
+
+    pub fn set_value(&mut self, value: Registers) {
+        self.text = value.display(DisplayMode::Factored).to_string();
+        self.error_text = None;
+        self.factoring = None;
+        self.value = value;
+    }
 }
 
 #[derive(Default)]
 pub struct UiStreamInput {
     next_frame_focus: bool,
     input_text: Option<String>,
-    value: Vec<Registers>,
+    error_text: Option<String>,
+    factoring: Option<Factoring>,
+    pub(super) value: Vec<Registers>,
 }
 
 impl UiStreamInput {
-    fn ui(&mut self, ui: &mut Ui, simulating: bool) {
+    fn ui(&mut self, ui: &mut Ui, simulating: bool, mode: DisplayMode) {
         ui.set_enabled(!simulating);
         self.value
-            .e_drain_where(|registers| ui.button(registers.to_string()).clicked())
+            .e_drain_where(|registers| ui.button(registers.display(mode).to_string()).clicked())
             .for_each(drop);
         if let Some(ref mut text) = self.input_text {
             let textedit = ui.text_edit_singleline(text);
@@ -80,21 +137,36 @@ impl UiStreamInput {
                 textedit.request_focus();
                 self.next_frame_focus = false;
             }
+            if let Some(ref err) = self.error_text {
+                ui.label(WidgetText::from(err).color(egui::Color32::RED));
+            }
+            if textedit.changed() {
+                self.error_text = None;
+            }
             if textedit.lost_focus() {
                 if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-                    if let Ok(registers) = text.parse() {
-                        self.value.push(registers);
+                    match Factoring::parse(text) {
+                        Ok(factoring) => {
+                            self.factoring = Some(factoring);
+                            text.clear();
+                        }
+                        Err(e) => self.error_text = Some(e.to_string()),
                     }
-                    text.clear();
                     self.next_frame_focus = true;
                 } else {
                     self.input_text = None;
+                    self.error_text = None;
                 }
             }
         } else if ui.button("Add to stream").clicked() {
             self.input_text = Some(String::new());
             self.next_frame_focus = true;
         }
+        match ui_factoring(ui, &mut self.factoring) {
+            Some(Ok(values)) => self.value.extend(values),
+            Some(Err(e)) => self.error_text = Some(e.to_string()),
+            None => (),
+        }
     }
 
     pub fn value(&self) -> &Vec<Registers> {
@@ -102,6 +174,422 @@ impl UiStreamInput {
     }
 }
 
+#[derive(Default)]
+pub struct UiBreakpoints {
+    text: String,
+    error_text: Option<String>,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl UiBreakpoints {
+    fn ui(&mut self, ui: &mut Ui) {
+        self.breakpoints
+            .e_drain_where(|breakpoint| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut breakpoint.enabled, breakpoint.condition.to_string());
+                    ui.button("🗑").on_hover_text("remove breakpoint").clicked()
+                })
+                .inner
+            })
+            .for_each(drop);
+
+        let text_edit = ui.text_edit_singleline(&mut self.text).on_hover_text(
+            "e.g. exp(3) > 10, divisible(35), value == 1, travelers > 5, or empty(4,2)",
+        );
+        if let Some(ref err) = self.error_text {
+            ui.label(WidgetText::from(err).color(egui::Color32::RED));
+        }
+        if text_edit.has_focus() && ui.input(|i| !i.keys_down.is_empty()) {
+            self.error_text = None;
+        }
+        if text_edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            match self.text.parse::<Condition>() {
+                Ok(condition) => {
+                    self.breakpoints.push(condition.into());
+                    self.text.clear();
+                }
+                Err(e) => self.error_text = Some(e.to_string()),
+            }
+        }
+    }
+
+    pub fn any_enabled(&self) -> bool {
+        self.breakpoints.iter().any(|breakpoint| breakpoint.enabled)
+    }
+
+    /// The breakpoints whose conditions currently hold
+    pub fn hit<'a>(&'a self, sim: &'a Simulator) -> impl Iterator<Item = &'a Breakpoint> {
+        self.breakpoints
+            .iter()
+            .filter(|breakpoint| breakpoint.is_hit(sim))
+    }
+}
+
+#[derive(Default)]
+pub struct UiTestSuite {
+    pub(super) cases: Vec<(TestCase, Option<TestResult>)>,
+    input_text: String,
+    stream_text: String,
+    output_text: String,
+    output_stream_text: String,
+    error_text: Option<String>,
+    /// The values of the case being added, and the length of its input stream
+    adding: Option<Factoring>,
+    adding_stream_len: usize,
+    /// The cases being run by "Run all", in the same order as `cases`
+    run: Option<BatchRun>,
+}
+
+fn stream_text(stream: &[Registers], mode: DisplayMode) -> String {
+    stream.iter().map(|registers| registers.display(mode)).join(",")
+}
+
+impl UiTestSuite {
+    /// Starts factoring the values of the case being added
+    fn start_adding(&mut self) -> Result<(), RegisterParseError> {
+        let (values, stream_len) = parse_case(
+            &self.input_text,
+            &self.stream_text,
+            &self.output_text,
+            &self.output_stream_text,
+        )?;
+        self.adding = Some(Factoring::start(values));
+        self.adding_stream_len = stream_len;
+        Ok(())
+    }
+
+    /// Replaces every case, dropping any results still being worked out for the old ones
+    pub(super) fn set_cases(&mut self, cases: Vec<TestCase>) {
+        self.run = None;
+        self.cases = cases.into_iter().map(|case| (case, None)).collect();
+    }
+
+    /// Fills in the results which have come in since the last frame
+    fn poll(&mut self, program: &Simulator) {
+        let Some(ref mut run) = self.run else {
+            return;
+        };
+        let start = get_time();
+        run.poll(|| get_time() - start < FRAME_WORK_BUDGET);
+        for (ix, (case, result)) in self.cases.iter_mut().enumerate() {
+            if let (None, Some(ran)) = (&result, run.result(ix)) {
+                *result = Some(case.judge(ran, program.mode));
+            }
+        }
+        if run.is_finished() {
+            self.run = None;
+        }
+    }
+
+    fn ui(&mut self, ui: &mut Ui, program: &Simulator, mode: DisplayMode) {
+        self.poll(program);
+        let l3x = program.mode == MatrixMode::L3X;
+        let running = self.run.is_some();
+        let mut removed = None;
+        egui::Grid::new("test cases").striped(true).show(ui, |ui| {
+            ui.label("Input");
+            if l3x {
+                ui.label("Stream");
+            }
+            ui.label("Expected");
+            ui.label("Actual");
+            ui.label("Steps");
+            ui.label("");
+            ui.end_row();
+
+            for (ix, (case, result)) in self.cases.iter().enumerate() {
+                ui.label(case.input.display(mode).to_string());
+                if l3x {
+                    ui.label(stream_text(&case.stream, mode));
+                }
+                let mut expected = case.expected_output.display(mode).to_string();
+                let mut actual = result
+                    .as_ref()
+                    .and_then(|r| r.output.as_ref())
+                    .map_or("none".to_string(), |output| output.display(mode).to_string());
+                if l3x {
+                    expected = format!("{expected} / {}", stream_text(&case.expected_stream, mode));
+                    if let Some(result) = result {
+                        actual = format!("{actual} / {}", stream_text(&result.output_stream, mode));
+                    }
+                }
+                ui.label(expected);
+                match result {
+                    Some(result) => {
+                        let (verdict, color, hover) = match result.verdict {
+                            Verdict::Passed => ("pass", egui::Color32::GREEN, String::new()),
+                            Verdict::Failed => ("fail", egui::Color32::RED, String::new()),
+                            Verdict::Errored(ref e) => ("error", egui::Color32::RED, e.to_string()),
+                            Verdict::TimedOut => (
+                                "timeout",
+                                egui::Color32::YELLOW,
+                                format!("did not halt within {MAX_TICKS} steps"),
+                            ),
+                        };
+                        ui.label(WidgetText::from(format!("{actual} ({verdict})")).color(color))
+                            .on_hover_text(hover);
+                        ui.label(result.steps.to_string());
+                    }
+                    None => {
+                        ui.label("");
+                        ui.label("");
+                    }
+                }
+                let remove = ui.add_enabled(!running, egui::Button::new("🗑"));
+                if remove.on_hover_text("remove test case").clicked() {
+                    removed = Some(ix);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(ix) = removed {
+            self.cases.remove(ix);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Input");
+            ui.text_edit_singleline(&mut self.input_text);
+        });
+        if l3x {
+            ui.horizontal(|ui| {
+                ui.label("Stream");
+                ui.text_edit_singleline(&mut self.stream_text);
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Expected output");
+            ui.text_edit_singleline(&mut self.output_text);
+        });
+        if l3x {
+            ui.horizontal(|ui| {
+                ui.label("Expected stream");
+                ui.text_edit_singleline(&mut self.output_stream_text);
+            });
+        }
+        if let Some(ref err) = self.error_text {
+            ui.label(WidgetText::from(err).color(egui::Color32::RED));
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.adding.is_none(), egui::Button::new("Add")).clicked() {
+                self.error_text = self.start_adding().err().map(|e| e.to_string());
+            }
+            if running {
+                if ui.button("Cancel").clicked() {
+                    self.run = None;
+                }
+            } else if ui.button("Run all").clicked() {
+                let cases = self.cases.iter().map(|(case, _)| case.clone()).collect_vec();
+                for (_, result) in &mut self.cases {
+                    *result = None;
+                }
+                self.run = Some(start_run(program, &cases, MAX_TICKS));
+            }
+        });
+        match ui_factoring(ui, &mut self.adding) {
+            Some(Ok(values)) => {
+                let case = TestCase::from_values(values, self.adding_stream_len);
+                self.cases.push((case, None));
+            }
+            Some(Err(e)) => self.error_text = Some(e.to_string()),
+            None => (),
+        }
+        if let Some(ref run) = self.run {
+            let (finished, total) = run.progress();
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(format!("Ran {finished} of {total} cases"));
+            });
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UiBatch {
+    inputs_text: String,
+    stream_text: String,
+    error_text: Option<String>,
+    /// The inputs followed by the stream, factored before the run starts
+    factoring: Option<Factoring>,
+    inputs_len: usize,
+    run: Option<BatchRun>,
+}
+
+impl UiBatch {
+    /// Starts factoring the inputs and stream
+    fn start(&mut self) -> Result<(), String> {
+        let mut values = parse_inputs(&self.inputs_text).map_err(|e| e.to_string())?;
+        self.inputs_len = values.len();
+        values.extend(parse_stream(&self.stream_text).map_err(|e| e.to_string())?);
+        self.run = None;
+        self.factoring = Some(Factoring::start(values));
+        Ok(())
+    }
+
+    /// Runs the program on the factored inputs
+    fn start_run(&mut self, program: &Simulator, mut inputs: Vec<Registers>) {
+        let stream = inputs.split_off(self.inputs_len);
+        let inputs = inputs
+            .into_iter()
+            .map(|input| (input, stream.clone()))
+            .collect();
+        self.run = Some(BatchRun::start(program, inputs, MAX_TICKS));
+    }
+
+    fn ui(&mut self, ui: &mut Ui, program: &Simulator, ctx: &mut AsyncContext, mode: DisplayMode) {
+        let l3x = program.mode == MatrixMode::L3X;
+        let running = self.factoring.is_some()
+            || self.run.as_ref().map(|run| !run.is_finished()).unwrap_or(false);
+
+        ui.horizontal(|ui| {
+            ui.label("Inputs");
+            ui.text_edit_singleline(&mut self.inputs_text)
+                .on_hover_text("comma-separated values and ranges, e.g. 3, 10..20, 2^0..2^500");
+        });
+        if l3x {
+            ui.horizontal(|ui| {
+                ui.label("Stream");
+                ui.text_edit_singleline(&mut self.stream_text);
+            });
+        }
+        if let Some(ref err) = self.error_text {
+            ui.label(WidgetText::from(err).color(egui::Color32::RED));
+        }
+
+        ui.horizontal(|ui| {
+            ui.scope(|ui| {
+                ui.set_enabled(!running);
+                if ui.button("Run batch").clicked() {
+                    self.error_text = self.start().err();
+                }
+            });
+            if self.run.is_some() && running && ui.button("Cancel").clicked() {
+                self.run = None;
+            }
+            if let Some(ref run) = self.run {
+                if ui.button("Export CSV").clicked() {
+                    match run.export_csv() {
+                        Ok(data) => ctx.start_file_export(data, ExportKind::BatchResults),
+                        Err(e) => log::error!("Could not export batch results: {e}"),
+                    }
+                }
+            }
+        });
+
+        match ui_factoring(ui, &mut self.factoring) {
+            Some(Ok(values)) => self.start_run(program, values),
+            Some(Err(e)) => self.error_text = Some(e.to_string()),
+            None => (),
+        }
+        let Some(ref mut run) = self.run else {
+            return;
+        };
+        let start = get_time();
+        run.poll(|| get_time() - start < FRAME_WORK_BUDGET);
+        let (finished, total) = run.progress();
+        ui.horizontal(|ui| {
+            if !run.is_finished() {
+                ui.spinner();
+            }
+            ui.label(format!("Ran {finished} of {total} inputs"));
+        });
+
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("batch results").striped(true).show(ui, |ui| {
+                ui.label("Input");
+                ui.label("Output");
+                if l3x {
+                    ui.label("Stream");
+                }
+                ui.label("Steps");
+                ui.end_row();
+
+                for result in run.results() {
+                    ui.label(result.input.display(mode).to_string());
+                    let output = result
+                        .output
+                        .as_ref()
+                        .map_or("none".to_string(), |output| output.display(mode).to_string());
+                    match result.outcome {
+                        Outcome::Halted => ui.label(output),
+                        Outcome::Errored(ref e) => ui
+                            .label(WidgetText::from(format!("{output} (error)")).color(egui::Color32::RED))
+                            .on_hover_text(e.to_string()),
+                        Outcome::TimedOut => ui
+                            .label(WidgetText::from(format!("{output} (timeout)")).color(egui::Color32::YELLOW))
+                            .on_hover_text(format!("did not halt within {MAX_TICKS} steps")),
+                    };
+                    if l3x {
+                        ui.label(stream_text(&result.output_stream, mode));
+                    }
+                    ui.label(result.steps.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct UiSymbolic {
+    input_text: String,
+    error_text: Option<String>,
+    results: Vec<PathResult>,
+    exploring: Option<Exploring>,
+}
+
+impl UiSymbolic {
+    fn ui(&mut self, ui: &mut Ui, program: &Simulator) {
+        ui.horizontal(|ui| {
+            ui.label("Input");
+            ui.text_edit_singleline(&mut self.input_text)
+                .on_hover_text("a product of factors whose powers may be unknowns, e.g. 2^x · 3^y · 5^2");
+        });
+        if let Some(ref err) = self.error_text {
+            ui.label(WidgetText::from(err).color(egui::Color32::RED));
+        }
+        if ui.add_enabled(self.exploring.is_none(), egui::Button::new("Explore")).clicked() {
+            let exploring = self
+                .input_text
+                .parse::<SymbolicValue>()
+                .map_err(|e| e.to_string())
+                .and_then(|input| Exploring::start(program, &input).map_err(|e| e.to_string()));
+            match exploring {
+                Ok(exploring) => {
+                    self.exploring = Some(exploring);
+                    self.error_text = None;
+                }
+                Err(e) => self.error_text = Some(e),
+            }
+        }
+        if let Some(ref mut exploring) = self.exploring {
+            let start = get_time();
+            if let Some(results) = exploring.poll(|| get_time() - start < FRAME_WORK_BUDGET) {
+                self.results = results.to_vec();
+                self.exploring = None;
+            } else {
+                let finished = exploring.finished_paths();
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("Exploring... {finished} paths finished"));
+                    if ui.button("Cancel").clicked() {
+                        self.exploring = None;
+                    }
+                });
+            }
+        }
+
+        for result in &self.results {
+            let color = match result.outcome {
+                PathOutcome::Output(_) => ui.visuals().text_color(),
+                PathOutcome::Loops | PathOutcome::GaveUp => egui::Color32::YELLOW,
+                _ => egui::Color32::RED,
+            };
+            ui.label(WidgetText::from(result.to_string()).color(color));
+        }
+    }
+}
+
 impl Matrix {
     fn ui_simulation_tools(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
@@ -114,7 +602,18 @@ impl Matrix {
             ui.scope(|ui| {
                 ui.set_enabled(self.simulating);
                 ui.scope(|ui| {
-                    ui.set_enabled(!self.stepping);
+                    ui.set_enabled(self.timeline.can_step_back(self.sim.ticks()) && !self.running_to_end);
+                    if ui
+                        .button("⏮")
+                        .on_hover_text("step back by one cycle")
+                        .on_disabled_hover_text("step back by one cycle")
+                        .clicked()
+                    {
+                        self.step_back()
+                    }
+                });
+                ui.scope(|ui| {
+                    ui.set_enabled(!self.stepping && self.sim.error().is_none());
                     if ui
                         .button("▶")
                         .on_hover_text("play (step automatically)")
@@ -135,14 +634,25 @@ impl Matrix {
                         self.stepping = false;
                     };
                 });
-                if ui
-                    .button("⏭")
-                    .on_hover_text("step by one cycle")
-                    .on_disabled_hover_text("step by one cycle")
-                    .clicked()
-                {
-                    self.step()
-                }
+                ui.scope(|ui| {
+                    ui.set_enabled(self.sim.error().is_none() && !self.running_to_end);
+                    if ui
+                        .button("⏭")
+                        .on_hover_text("step by one cycle")
+                        .on_disabled_hover_text("step by one cycle")
+                        .clicked()
+                    {
+                        self.step()
+                    }
+                    if ui
+                        .button("⏩")
+                        .on_hover_text("run until the program halts")
+                        .on_disabled_hover_text("run until the program halts")
+                        .clicked()
+                    {
+                        self.run_to_end()
+                    }
+                });
                 if ui
                     .button("⏹")
                     .on_hover_text("exit the simulation")
@@ -150,27 +660,107 @@ impl Matrix {
                     .clicked()
                 {
                     self.cleanup_simulation();
-                    self.stepping = false;
+                    self.cancel_run_to_end();
                 }
             });
             ui.separator();
             ui.scope(|ui| {
                 ui.set_enabled(!self.simulating);
                 ui.horizontal(|ui| {
-                    let l3_radio = ui.radio_value(&mut self.mode, MatrixMode::L3, "L3");
-                    let l3x_radio = ui.radio_value(&mut self.mode, MatrixMode::L3X, "L3X");
+                    let l3_radio = ui.radio_value(&mut self.sim.mode, MatrixMode::L3, "L3");
+                    let l3x_radio = ui.radio_value(&mut self.sim.mode, MatrixMode::L3X, "L3X");
                     if l3_radio.union(l3x_radio).changed() {
-                        self.dims = self.dims.max(self.mode.minimum_size());
+                        self.sim.dims = self.sim.dims.max(self.sim.mode.minimum_size());
                         self.force_queue_l3x()
                     }
                 });
             });
         });
 
+        if self.running_to_end {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(format!("Running... tick {}", self.sim.ticks()));
+                if ui.button("Cancel").clicked() {
+                    self.cancel_run_to_end();
+                }
+            });
+        }
+
+        ui.scope(|ui| {
+            ui.set_enabled(!self.simulating);
+            ui.horizontal(|ui| {
+                ui.label("Rules");
+                ui.radio_value(&mut self.sim.ruleset, Ruleset::Relaxed, "Relaxed")
+                    .on_hover_text("lift the limits from the spec");
+                ui.radio_value(&mut self.sim.ruleset, Ruleset::Competition, "Competition")
+                    .on_hover_text("enforce the limits from the CMIMC problem statement");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Queues");
+                ui.set_enabled(!self.sim.ruleset.strict_queues());
+                ui.radio_value(
+                    &mut self.sim.queue_semantics,
+                    QueueSemantics::Relaxed,
+                    "Relaxed",
+                )
+                .on_hover_text("a number may enter a queue on the same tick that another pops from it");
+                ui.radio_value(
+                    &mut self.sim.queue_semantics,
+                    QueueSemantics::Strict,
+                    "Strict",
+                )
+                .on_hover_text("follow the spec: numbers on a queue collide, and empty queues cannot be popped")
+                .on_disabled_hover_text("the competition ruleset always uses strict queues");
+            });
+            ui.checkbox(&mut self.sim.tracing, "Record trace")
+                .on_hover_text(
+                    "record every tick, so that it can be exported from the import tools",
+                );
+            ui.checkbox(&mut self.sim.skip_loops, "Skip simple loops")
+                .on_hover_text(
+                    "jump over loops which multiply a lone number by the same amount on every pass; \
+                    loops are stepped through as usual while a breakpoint or watch point is set",
+                );
+        });
+        for violation in self.sim.rule_violations() {
+            ui.label(WidgetText::from(violation.to_string()).color(egui::Color32::YELLOW));
+        }
+
         ui.horizontal(|ui| {
             ui.label("Simulation rate (in frame time)");
             ui.add(egui::widgets::Slider::new(&mut self.period, 1..=120))
         });
+        ui.checkbox(&mut self.heatmap, "Heatmap")
+            .on_hover_text("shade cells by how often travelers have entered them");
+        ui.horizontal(|ui| {
+            ui.label("Show values as");
+            let mode = &mut self.display_mode;
+            ui.radio_value(mode, DisplayMode::Decimal, "Decimal")
+                .on_hover_text("cut short after a few digits when they get long");
+            ui.radio_value(mode, DisplayMode::Factored, "Factored")
+                .on_hover_text("a product of prime powers, such as 2^10·3^5");
+            ui.radio_value(mode, DisplayMode::Exponents, "Exponents")
+                .on_hover_text("the powers of 2, 3, 5, 7, ... in order, such as ⟨10, 5⟩");
+        });
+
+        if let Some((first, last)) = self.timeline.range() {
+            ui.horizontal(|ui| {
+                ui.label("Tick");
+                let mut tick = self.sim.ticks();
+                if ui
+                    .add(egui::widgets::Slider::new(&mut tick, first..=last))
+                    .on_hover_text("scrub through the ticks simulated so far")
+                    .changed()
+                {
+                    self.seek(tick);
+                }
+            });
+        }
+
+        if let Some(error) = self.sim.error() {
+            ui.label(WidgetText::from(error.to_string()).color(egui::Color32::RED));
+        }
     }
 
     fn ui_cell_value_view(&mut self, ui: &mut Ui, location: IVec2) {
@@ -193,7 +783,8 @@ impl Matrix {
                         log::warn!("In L3X mode, edited square *must* be a queue!")
                     } else {
                         //self.instructions.insert(location, serialize_success);
-                        self.apply(MatrixAction::Paste(location,super::L3XData { data: vec![vec![crate::l3x::MaybeL3X::Some(serialize_success)]], dims:uvec2(1,1) }));
+                        let super::L3XData { colors, comments, .. } = self.peek(location.into());
+                        self.apply(MatrixAction::Paste(location,super::L3XData { data: vec![vec![crate::l3x::MaybeL3X::Some(serialize_success)]], colors, comments, dims:uvec2(1,1) }));
                     }
                 } else {
                     log::warn!("Serialization failure")
@@ -201,41 +792,77 @@ impl Matrix {
             }
             if ui.button("Clear").clicked() {
                 self.selecting_text.clear();
-                self.instructions.remove(&location);
+                self.sim.instructions.remove(&location);
                 self.force_queue_l3x();
             }
         });
     }
 
+    fn ui_comment(&mut self, ui: &mut Ui, selection: Selection) {
+        ui.label(format!("Comment on {}", selection.name()));
+        let text = self.comments.entry(selection).or_default();
+        ui.text_edit_multiline(text)
+            .on_hover_text("shown when hovering over the cells, and saved in project files");
+        if text.is_empty() {
+            self.comments.remove(&selection);
+        }
+
+        // comments on larger or smaller areas which share the selected cell
+        let mut others = self
+            .comments
+            .iter()
+            .filter(|&(area, _)| *area != selection && area.contains(selection.starts))
+            .map(|(area, text)| format!("{}: {text}", area.name()))
+            .collect_vec();
+        others.sort();
+        for other in others {
+            ui.label(WidgetText::from(other).weak());
+        }
+    }
+
     fn ui_cell_traveler_view(&mut self, ui: &mut Ui, location: IVec2) {
         ui.label("Travelers on this cell");
-        self.travelers
+        self.sim
+            .travelers()
             .iter()
             .filter(|&&Traveler { location: loc, .. }| loc == location)
             .for_each(|traveler| {
-                ui.label(traveler.to_string());
+                ui.label(traveler.describe(self.display_mode));
             });
 
-        if let Some(queue) = self.queues.get(&location) {
+        let stats = self
+            .sim
+            .cell_stats()
+            .get(&location)
+            .copied()
+            .unwrap_or_default();
+        ui.separator();
+        ui.label(format!("Entered {} times", stats.visits));
+        ui.label(format!(
+            "Left by the major output {} times and the minor output {} times",
+            stats.major_exits, stats.minor_exits
+        ));
+
+        if let Some(queue) = self.sim.queues().get(&location) {
             ui.separator();
             ui.label("Queue on this cell");
             for register in queue {
-                ui.label(register.to_string());
+                ui.label(register.display(self.display_mode).to_string());
             }
         }
     }
 
     fn ui_output_view(&mut self, ui: &mut Ui) {
         ui.label("Output");
-        if let Some(ref register) = self.output {
-            ui.label(register.to_string());
+        if let Some(register) = self.sim.output() {
+            ui.label(register.display(self.display_mode).to_string());
         }
 
-        if self.mode == MatrixMode::L3X {
+        if self.sim.mode == MatrixMode::L3X {
             ui.separator();
             ui.label("Output stream");
-            for register in &self.output_stream {
-                ui.label(register.to_string());
+            for register in self.sim.output_stream() {
+                ui.label(register.display(self.display_mode).to_string());
             }
         }
     }
@@ -264,7 +891,9 @@ impl Matrix {
         if ui.button("clear").clicked() {
             //self.transpose();
             if let Some(selection)=self.selecting {
-                self.apply(MatrixAction::Paste(selection.starts,super::L3XData { data: vec![vec![MaybeL3X::None;selection.width() as usize];selection.height() as usize], dims: uvec2(selection.width() as u32,selection.height() as u32) }));
+                // only the instructions are cleared, the colors and comments stay
+                let super::L3XData { colors, comments, .. } = self.peek(selection);
+                self.apply(MatrixAction::Paste(selection.starts,super::L3XData { data: vec![vec![MaybeL3X::None;selection.width() as usize];selection.height() as usize], colors, comments, dims: uvec2(selection.width() as u32,selection.height() as u32) }));
             }
         }
         if ui.button("copy").clicked() {
@@ -286,6 +915,24 @@ impl Matrix {
         if ui.button("undo").clicked() {
             self.undo();
         }
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Color");
+            let mut chosen = None;
+            for color in CellColor::ALL {
+                let swatch = egui::Button::new("  ").fill(color.swatch());
+                if ui.add(swatch).on_hover_text(color.name()).clicked() {
+                    chosen = Some(Some(color));
+                }
+            }
+            if ui.button("none").on_hover_text("remove the color").clicked() {
+                chosen = Some(None);
+            }
+            if let (Some(color), Some(selection)) = (chosen, self.selecting) {
+                let mut data = self.peek(selection);
+                data.colors = vec![vec![color; selection.width() as usize]; selection.height() as usize];
+                self.apply(MatrixAction::Paste(selection.starts, data));
+            }
+        });
     }
 
     fn ui_import(&mut self, ui: &mut Ui, ctx: &mut AsyncContext) {
@@ -298,10 +945,103 @@ impl Matrix {
             });
             if ui.button("Export").clicked() {
                 if let Ok(data) = self.export_data() {
-                    ctx.start_file_export(data, self.mode)
+                    ctx.start_file_export(data, ExportKind::Program(self.sim.mode))
+                }
+            };
+            if ui
+                .button("Export project")
+                .on_hover_text("save the program along with its inputs, tests, notes and view settings")
+                .clicked()
+            {
+                match self.export_project() {
+                    Ok(data) => ctx.start_file_export(data, ExportKind::Project),
+                    Err(e) => log::error!("Could not export project: {e}"),
                 }
             };
         });
+        ui.horizontal(|ui| {
+            ui.label("Export directions as");
+            let letters = &mut self.export_style.letters;
+            ui.radio_value(letters, DirectionLetters::Udlr, "UDLR");
+            ui.radio_value(letters, DirectionLetters::Nsew, "NSEW");
+            ui.checkbox(&mut self.export_style.lowercase, "lowercase");
+        })
+        .response
+        .on_hover_text("Cells which are unchanged since import keep their original spelling");
+        self.ui_import_report(ui);
+        if !self.sim.trace().is_empty() {
+            ui.label(format!(
+                "{} trace events recorded",
+                self.sim.trace().events().len()
+            ));
+            ui.horizontal(|ui| {
+                ui.label("Export trace as");
+                for format in [TraceFormat::JsonLines, TraceFormat::Csv] {
+                    if ui.button(ExportKind::Trace(format).filter_name()).clicked() {
+                        match self.sim.trace().export(format) {
+                            Ok(data) => ctx.start_file_export(data, ExportKind::Trace(format)),
+                            Err(e) => log::error!("Could not export trace: {e}"),
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    fn ui_import_report(&mut self, ui: &mut Ui) {
+        let Some(ref mut report) = self.import_report else {
+            return;
+        };
+        ui.label(
+            WidgetText::from(format!(
+                "{} cells of the imported file could not be read",
+                report.issues.len()
+            ))
+            .color(egui::Color32::RED),
+        );
+        let mut jump_to = None;
+        egui::ScrollArea::vertical()
+            .max_height(150.0)
+            .show(ui, |ui| {
+                for issue in &report.issues {
+                    ui.horizontal(|ui| {
+                        ui.label(issue.to_string());
+                        ui.scope(|ui| {
+                            ui.set_enabled(report.pending.is_none());
+                            if ui
+                                .button("Go to")
+                                .on_disabled_hover_text("import the valid cells first")
+                                .clicked()
+                            {
+                                jump_to = Some(issue.location);
+                            }
+                        });
+                    });
+                }
+            });
+
+        let (mut load, mut close) = (false, false);
+        ui.horizontal(|ui| {
+            if report.pending.is_some() {
+                ui.set_enabled(!self.simulating);
+                load = ui.button("Import valid cells").clicked();
+                close = ui.button("Keep current program").clicked();
+            } else {
+                close = ui.button("Dismiss").clicked();
+            }
+        });
+
+        if load {
+            if let Some(program) = report.pending.take() {
+                self.load_program(program);
+            }
+        }
+        if close {
+            self.import_report = None;
+        }
+        if let Some(location) = jump_to {
+            self.selecting = Some(location.into());
+        }
     }
 
     fn ui_sound(&mut self, ui: &mut Ui) {
@@ -330,23 +1070,44 @@ impl Matrix {
             self.ui_import(ui, executor);
         });
 
+        ui.separator();
+        ui.collapsing("Notes", |ui| {
+            ui.text_edit_multiline(&mut self.notes)
+                .on_hover_text("saved with the program when exporting a project");
+        });
+
         ui.separator();
         ui.collapsing_open("Single input", |ui| {
-            self.single_input.ui(ui, self.simulating)
+            self.single_input.ui(ui, self.simulating, self.display_mode)
         });
 
-        if self.mode == MatrixMode::L3X {
+        if self.sim.mode == MatrixMode::L3X {
             ui.separator();
             ui.collapsing_open("Multi input (L3X)", |ui| {
-                self.stream_input.ui(ui, self.simulating)
+                self.stream_input.ui(ui, self.simulating, self.display_mode)
             });
         }
 
+        ui.separator();
+        ui.collapsing_open("Breakpoints", |ui| self.breakpoints.ui(ui));
+
+        ui.separator();
+        ui.collapsing_open("Test cases", |ui| self.test_suite.ui(ui, &self.sim, self.display_mode));
+
+        ui.separator();
+        ui.collapsing_open("Batch run", |ui| self.batch.ui(ui, &self.sim, executor, self.display_mode));
+
+        if self.sim.mode == MatrixMode::L3 {
+            ui.separator();
+            ui.collapsing_open("Symbolic execution", |ui| self.symbolic.ui(ui, &self.sim));
+        }
+
         ui.separator();
         ui.collapsing_open("Matrix editing", |ui| {
             self.ui_edit_matrix(ui);
             if let Some(location) = self.selecting {
                 ui.scope(|ui| self.ui_cell_value_view(ui, location.starts));
+                self.ui_comment(ui, location);
             }
         });
 
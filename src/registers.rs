@@ -1,18 +1,136 @@
 use std::{
+    cmp::Ordering,
     fmt::Display,
-    ops::{Mul, MulAssign},
+    ops::{Add, Mul, MulAssign},
     str::FromStr,
 };
 
 use itertools::{merge_join_by, Itertools};
 use num_bigint::{BigUint, ParseBigIntError};
+use serde::{Deserialize, Serialize};
+
+/// The power of a prime in [Registers]. Powers are kept in a u64 for as long as they fit, and are
+/// only promoted to a [BigUint] once they grow past that, so that arithmetic on them can never
+/// overflow.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Exponent {
+    Small(u64),
+    /// Always greater than `u64::MAX`
+    Big(BigUint),
+}
+
+impl Exponent {
+    pub const ZERO: Self = Exponent::Small(0);
+
+    fn from_big(value: BigUint) -> Self {
+        match value.to_u64_digits()[..] {
+            [] => Exponent::Small(0),
+            [small] => Exponent::Small(small),
+            _ => Exponent::Big(value),
+        }
+    }
+
+    fn to_big(&self) -> BigUint {
+        match self {
+            Exponent::Small(small) => BigUint::from(*small),
+            Exponent::Big(big) => big.clone(),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    /// The exponent, if it fits in a u64
+    pub fn to_u64(&self) -> Option<u64> {
+        match self {
+            Exponent::Small(small) => Some(*small),
+            Exponent::Big(_) => None,
+        }
+    }
+
+    pub fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        match (self, rhs) {
+            (Exponent::Small(left), Exponent::Small(right)) => {
+                left.checked_sub(*right).map(Exponent::Small)
+            }
+            _ => (self >= rhs).then(|| Self::from_big(self.to_big() - rhs.to_big())),
+        }
+    }
+}
+
+impl From<u64> for Exponent {
+    fn from(value: u64) -> Self {
+        Exponent::Small(value)
+    }
+}
+
+impl From<u32> for Exponent {
+    fn from(value: u32) -> Self {
+        Exponent::Small(value.into())
+    }
+}
+
+impl Ord for Exponent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Exponent::Small(left), Exponent::Small(right)) => left.cmp(right),
+            (Exponent::Small(_), Exponent::Big(_)) => Ordering::Less,
+            (Exponent::Big(_), Exponent::Small(_)) => Ordering::Greater,
+            (Exponent::Big(left), Exponent::Big(right)) => left.cmp(right),
+        }
+    }
+}
+
+impl PartialOrd for Exponent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &Exponent {
+    type Output = Exponent;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Exponent::Small(left), Exponent::Small(right)) => match left.checked_add(*right) {
+                Some(sum) => Exponent::Small(sum),
+                None => Exponent::Big(BigUint::from(*left) + right),
+            },
+            _ => Exponent::from_big(self.to_big() + rhs.to_big()),
+        }
+    }
+}
+
+impl Mul<u64> for &Exponent {
+    type Output = Exponent;
+
+    fn mul(self, rhs: u64) -> Self::Output {
+        match self {
+            Exponent::Small(small) => match small.checked_mul(rhs) {
+                Some(product) => Exponent::Small(product),
+                None => Exponent::Big(BigUint::from(*small) * rhs),
+            },
+            Exponent::Big(big) => Exponent::from_big(big * rhs),
+        }
+    }
+}
+
+impl Display for Exponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exponent::Small(small) => write!(f, "{small}"),
+            Exponent::Big(big) => write!(f, "{big}"),
+        }
+    }
+}
 
 /// Represents the value of a traveler or a cell. If the value of the register is 1, then the
 /// vector in this struct is empty. Otherwise, it is a list of (p, pow), where p is a prime,
 /// representing `p_0 ^ pow_0 * p_1 ^ pow_1 * ... * p_n ^ pow_n`, where p_0, p_1, ... p_n are in
 /// ascending order.
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub struct Registers(pub Vec<(u64, u32)>);
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct Registers(pub Vec<(u64, Exponent)>);
 
 impl Registers {
     pub const ONE: Self = Registers(vec![]);
@@ -21,7 +139,45 @@ impl Registers {
         self.0.is_empty()
     }
 
-    fn update_entry(&mut self, prime: u64, pow: u32) {
+    /// The value of the register, if it fits in a u64
+    pub fn to_u64(&self) -> Option<u64> {
+        self.0.iter().try_fold(1u64, |acc, (base, pow)| {
+            let pow = u32::try_from(pow.to_u64()?).ok()?;
+            base.checked_pow(pow).and_then(|p| acc.checked_mul(p))
+        })
+    }
+
+    /// The power of the given prime in the factorization of this register
+    pub fn exponent(&self, prime: u64) -> Exponent {
+        self.0
+            .binary_search_by_key(&prime, |&(prime, _)| prime)
+            .map(|ix| self.0[ix].1.clone())
+            .unwrap_or(Exponent::ZERO)
+    }
+
+    /// This register raised to the given power
+    pub fn pow(&self, exp: u64) -> Self {
+        if exp == 0 {
+            return Self::ONE;
+        }
+        Registers(
+            self.0
+                .iter()
+                .map(|(base, pow)| (*base, pow * exp))
+                .collect(),
+        )
+    }
+
+    /// Changes the power of the given prime, leaving it out if the power is zero
+    pub fn set_exponent(&mut self, prime: u64, pow: Exponent) {
+        if pow.is_zero() {
+            self.0.retain(|&(p, _)| p != prime);
+        } else {
+            self.update_entry(prime, pow);
+        }
+    }
+
+    fn update_entry(&mut self, prime: u64, pow: Exponent) {
         match self.0.binary_search_by_key(&prime, |&(prime, _)| prime) {
             Ok(ix) => self.0[ix].1 = pow,
             Err(ix) => self.0.insert(ix, (prime, pow)),
@@ -29,22 +185,40 @@ impl Registers {
     }
 }
 
+/// Builds a register from (p, pow) pairs, which must already be in ascending order of p
+impl From<Vec<(u64, u64)>> for Registers {
+    fn from(value: Vec<(u64, u64)>) -> Self {
+        Registers(
+            value
+                .into_iter()
+                .map(|(prime, pow)| (prime, Exponent::from(pow)))
+                .collect(),
+        )
+    }
+}
+
 impl Default for Registers {
     fn default() -> Self {
         Self::ONE
     }
 }
 
-#[derive(thiserror::Error, Debug, PartialEq)]
+#[derive(thiserror::Error, Debug, PartialEq, Clone)]
 pub enum RegisterParseError {
     #[error("A register set cannot have a value of zero. Perhaps you meant p^0, or 1.")]
     Zero,
     #[error("The given number has prime factors which do not fit in a u64")]
     FactorTooLarge,
-    #[error("The given number has a power which does not fit in a u32")]
-    PowTooLarge,
     #[error("Failed to parse bigint with error: {0}")]
     BigInt(#[from] ParseBigIntError),
+    #[error("Could not parse the power {0:?}")]
+    BadExponent(String),
+    #[error("Expected p:pow, found {0:?}")]
+    BadEntry(String),
+    #[error("{0:?} is not a prime which fits in a u64")]
+    NotPrime(String),
+    #[error("The prime {0} is given more than once")]
+    RepeatedPrime(u64),
 }
 
 impl TryFrom<BigUint> for Registers {
@@ -68,13 +242,7 @@ impl TryFrom<BigUint> for Registers {
                             x if x > 1 => Self::Error::FactorTooLarge,
                             _ => panic!("either itertools or bigint just failed"),
                         })?;
-
-                    let pow = if pow <= u32::MAX as usize {
-                        pow as u32
-                    } else {
-                        return Err(Self::Error::PowTooLarge);
-                    };
-                    Ok((factor, pow))
+                    Ok((factor, Exponent::from(pow as u64)))
                 })
                 .try_collect::<_, Vec<_>, _>()
                 .map(Self)
@@ -90,22 +258,208 @@ impl TryFrom<u64> for Registers {
     }
 }
 
+/// Splits a comma-separated list of values, leaving the commas inside `{p:pow, ...}` alone
+pub fn split_list(s: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    s.split(move |c| {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => (),
+        }
+        c == ',' && depth == 0
+    })
+}
+
+impl Registers {
+    /// Parses `{p:pow, ...}`, where every p is a prime
+    fn parse_map(entries: &str) -> Result<Self, RegisterParseError> {
+        let mut result = vec![];
+        for entry in entries.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (prime, pow) = entry
+                .split_once(':')
+                .ok_or_else(|| RegisterParseError::BadEntry(entry.to_string()))?;
+            let prime = prime
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|&p| num_prime::nt_funcs::is_prime64(p))
+                .ok_or_else(|| RegisterParseError::NotPrime(prime.trim().to_string()))?;
+            let pow = pow
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| RegisterParseError::BadExponent(pow.trim().to_string()))?;
+            result.push((prime, pow));
+        }
+        result.sort_unstable();
+        if let Some(&[(prime, _), _]) = result.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(RegisterParseError::RepeatedPrime(prime));
+        }
+        result.retain(|&(_, pow)| pow != 0);
+        Ok(Registers::from(result))
+    }
+
+    /// Parses `n^pow * ...`, only factoring each n rather than the whole product
+    fn parse_product(s: &str) -> Result<Self, RegisterParseError> {
+        parse_powers(s)?
+            .into_iter()
+            .try_fold(Registers::ONE, |acc, (base, pow)| {
+                Ok(acc * Registers::try_from(base)?.pow(pow))
+            })
+    }
+}
+
+/// Splits `n^pow * ...` into each n with its power, without factoring anything
+pub fn parse_powers(s: &str) -> Result<Vec<(BigUint, u64)>, RegisterParseError> {
+    s.split(['*', '·'])
+        .map(|factor| {
+            let (base, pow) = match factor.split_once('^') {
+                Some((base, pow)) => {
+                    let pow = pow
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|_| RegisterParseError::BadExponent(pow.trim().to_string()))?;
+                    (base, pow)
+                }
+                None => (factor, 1),
+            };
+            Ok((base.trim().parse::<BigUint>()?, pow))
+        })
+        .collect()
+}
+
+/// Parses a decimal number, a product of powers such as `2^10 * 3^5`, or a map from primes to
+/// their powers such as `{2:10, 3:5}`. The last two never factor anything larger than their bases,
+/// so they are the only practical way to write very large numbers.
 impl FromStr for Registers {
     type Err = RegisterParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.parse::<BigUint>()
-            .map_err(Self::Err::BigInt)
-            .and_then(Registers::try_from)
+        let s = s.trim();
+        if let Some(entries) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            Registers::parse_map(entries)
+        } else if s.contains(['*', '·', '^']) {
+            Registers::parse_product(s)
+        } else {
+            s.parse::<BigUint>()
+                .map_err(Self::Err::BigInt)
+                .and_then(Registers::try_from)
+        }
+    }
+}
+
+/// Values with more decimal digits than this are written as a product of prime powers instead,
+/// since working out all of their digits would take too long
+const MAX_DECIMAL_DIGITS: f64 = 100_000.0;
+
+/// How many digits a decimal value may have before it is cut short in the IDE
+const SHOWN_DIGITS: usize = 24;
+
+/// Values whose largest prime is beyond this are shown as a map of powers rather than a vector
+const MAX_VECTOR_PRIME: u64 = 100;
+
+impl Registers {
+    /// The base 10 logarithm of the value, which is one less than its number of digits, give or
+    /// take rounding
+    fn log10(&self) -> f64 {
+        self.0
+            .iter()
+            .map(|(base, pow)| match pow.to_u64() {
+                Some(pow) => pow as f64 * (*base as f64).log10(),
+                None => f64::INFINITY,
+            })
+            .sum()
+    }
+
+    /// Multiplies the value out. Should only be called once the number of digits is known to be
+    /// reasonable.
+    fn to_biguint(&self) -> BigUint {
+        self.0.iter().fold(BigUint::from(1u64), |st, (base, pow)| {
+            st * BigUint::from(*base).pow(pow.to_u64().unwrap_or_default() as u32)
+        })
+    }
+
+    /// Formats the value for showing to the user, in the given mode
+    pub fn display(&self, mode: DisplayMode) -> impl Display + '_ {
+        Shown { value: self, mode }
+    }
+
+    /// Writes the value as a product of prime powers, such as `2^10·3^5·7`
+    fn fmt_factored(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_one() {
+            return write!(f, "1");
+        }
+        let factors = self.0.iter().format_with("·", |(base, pow), f| match pow {
+            Exponent::Small(1) => f(base),
+            _ => f(&format_args!("{base}^{pow}")),
+        });
+        write!(f, "{factors}")
     }
 }
 
 impl Display for Registers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let u64_repr = self.0.iter().fold(BigUint::from(1u64), |st, &(base, pow)| {
-            st * BigUint::from(base).pow(pow)
-        });
-        write!(f, "{u64_repr}")
+        if self.log10() > MAX_DECIMAL_DIGITS {
+            self.fmt_factored(f)
+        } else {
+            write!(f, "{}", self.to_biguint())
+        }
+    }
+}
+
+/// How values are shown in the IDE. Exported files never depend on the mode chosen, so that they
+/// can always be read back in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayMode {
+    /// Written out in full, or cut short with a count of the digits
+    #[default]
+    Decimal,
+    /// A product of prime powers, such as `2^10·3^5`
+    Factored,
+    /// The powers of 2, 3, 5, 7, ... in order, such as `⟨10, 5⟩`
+    Exponents,
+}
+
+struct Shown<'a> {
+    value: &'a Registers,
+    mode: DisplayMode,
+}
+
+impl Display for Shown<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.value;
+        let largest = value.0.last().map(|&(prime, _)| prime).unwrap_or(1);
+        let log10 = value.log10();
+        match self.mode {
+            DisplayMode::Decimal if log10 < SHOWN_DIGITS as f64 => write!(f, "{value}"),
+            DisplayMode::Decimal if log10.is_finite() => {
+                // only the leading digits are needed, which the logarithm is precise enough for,
+                // and multiplying out a long value on every frame would be too slow
+                let mantissa = 10f64.powf(log10.fract());
+                write!(
+                    f,
+                    "{mantissa:.5}…e{} (about {} digits)",
+                    log10.floor(),
+                    log10.floor() + 1.0
+                )
+            }
+            DisplayMode::Decimal | DisplayMode::Factored => value.fmt_factored(f),
+            DisplayMode::Exponents if largest <= MAX_VECTOR_PRIME => {
+                let powers = (2..=largest)
+                    .filter(|&p| num_prime::nt_funcs::is_prime64(p))
+                    .map(|p| value.exponent(p))
+                    .format(", ");
+                write!(f, "⟨{powers}⟩")
+            }
+            DisplayMode::Exponents => {
+                let powers = value
+                    .0
+                    .iter()
+                    .format_with(", ", |(base, pow), f| f(&format_args!("{base}:{pow}")));
+                write!(f, "{{{powers}}}")
+            }
+        }
     }
 }
 
@@ -117,13 +471,13 @@ impl Registers {
             |(left_base, _), (right_base, _)| left_base.cmp(right_base),
         )
         .map(|cmp| match cmp {
-            itertools::EitherOrBoth::Both(&(base, pow_left), &(_, pow_right)) => {
-                pow_left.checked_sub(pow_right).map(|pow| (base, pow))
+            itertools::EitherOrBoth::Both((base, pow_left), (_, pow_right)) => {
+                pow_left.checked_sub(pow_right).map(|pow| (*base, pow))
             }
-            itertools::EitherOrBoth::Left(&val) => Some(val),
+            itertools::EitherOrBoth::Left(val) => Some(val.clone()),
             itertools::EitherOrBoth::Right(_) => None,
         })
-        .filter(|u| u.map(|(_, pow)| pow != 0).unwrap_or(true))
+        .filter(|u| u.as_ref().map(|(_, pow)| !pow.is_zero()).unwrap_or(true))
         .collect::<Option<Vec<_>>>()?;
 
         Some(Self(result))
@@ -145,7 +499,7 @@ impl Mul for &Registers {
                 (*base_left, pow_left + pow_right)
             }
             itertools::EitherOrBoth::Left((a, b)) | itertools::EitherOrBoth::Right((a, b)) => {
-                (*a, *b)
+                (*a, b.clone())
             }
         })
         .collect();
@@ -194,7 +548,7 @@ mod test_registers {
         // very large
         assert_eq!(
             Registers::try_from(18_446_744_073_709_551_557),
-            Ok(Registers(vec![(18_446_744_073_709_551_557, 1)]))
+            Ok(Registers::from(vec![(18_446_744_073_709_551_557, 1)]))
         )
     }
 
@@ -202,35 +556,35 @@ mod test_registers {
     fn create() {
         assert_eq!(
             Registers::from_str("30"),
-            Ok(Registers(vec![(2, 1), (3, 1), (5, 1)]))
+            Ok(Registers::from(vec![(2, 1), (3, 1), (5, 1)]))
         )
     }
     #[test]
     fn multiplication() {
-        let r1 = Registers(vec![(2, 1), (5, 1)]);
-        let r2 = Registers(vec![(3, 1), (7, 1)]);
+        let r1 = Registers::from(vec![(2, 1), (5, 1)]);
+        let r2 = Registers::from(vec![(3, 1), (7, 1)]);
         assert_eq!(
             r1 * r2,
-            Registers(vec![(2, 1), (3, 1), (5, 1), (7, 1)]),
+            Registers::from(vec![(2, 1), (3, 1), (5, 1), (7, 1)]),
             "appending disjoint registers together"
         );
 
-        let r1 = Registers(vec![(2, 3), (3, 2), (7, 9)]);
-        let r2 = Registers(vec![(2, 6), (3, 4), (5, 1)]);
+        let r1 = Registers::from(vec![(2, 3), (3, 2), (7, 9)]);
+        let r2 = Registers::from(vec![(2, 6), (3, 4), (5, 1)]);
         assert_eq!(
             r1 * r2,
-            Registers(vec![(2, 9), (3, 6), (5, 1), (7, 9)]),
+            Registers::from(vec![(2, 9), (3, 6), (5, 1), (7, 9)]),
             "adding between registers 2 and 3"
         )
     }
 
     #[test]
     fn division() {
-        let r1 = Registers(vec![(2, 1), (3, 1), (5, 1)]);
-        let r2 = Registers(vec![(3, 1)]);
+        let r1 = Registers::from(vec![(2, 1), (3, 1), (5, 1)]);
+        let r2 = Registers::from(vec![(3, 1)]);
         assert_eq!(
             r1.try_div(&r2),
-            Some(Registers(vec![(2, 1), (5, 1)])),
+            Some(Registers::from(vec![(2, 1), (5, 1)])),
             "Factor should be allowed to divide a number"
         );
         assert_eq!(
@@ -239,12 +593,96 @@ mod test_registers {
             "a number cannot divide a lesser number"
         );
 
-        let r1 = Registers(vec![(2, 9), (3, 4), (5, 7), (7, 4)]);
-        let r2 = Registers(vec![(2, 1), (3, 1), (7, 1)]);
+        let r1 = Registers::from(vec![(2, 9), (3, 4), (5, 7), (7, 4)]);
+        let r2 = Registers::from(vec![(2, 1), (3, 1), (7, 1)]);
         assert_eq!(
             r1.try_div(&r2),
-            Some(Registers(vec![(2, 8), (3, 3), (5, 7), (7, 3)])),
+            Some(Registers::from(vec![(2, 8), (3, 3), (5, 7), (7, 3)])),
             "Normal case"
         );
     }
+
+    #[test]
+    fn expressions() {
+        let expected = Ok(Registers::from(vec![(2, 10), (3, 5)]));
+        assert_eq!(Registers::from_str("2^10 * 3^5"), expected);
+        assert_eq!(Registers::from_str("2^7·6^3 * 3^2"), expected);
+        assert_eq!(Registers::from_str(" {3:5, 2:10, 5:0} "), expected);
+        assert_eq!(
+            Registers::from_str("2^100000").map(|r| r.exponent(2)),
+            Ok(Exponent::from(100_000u64))
+        );
+        assert_eq!(Registers::from_str("7^0 * 1"), Ok(Registers::ONE));
+        assert_eq!(Registers::from_str("{}"), Ok(Registers::ONE));
+        assert_eq!(
+            Registers::from_str("2^3 * 0"),
+            Err(RegisterParseError::Zero)
+        );
+        assert_eq!(
+            Registers::from_str("{4:1}"),
+            Err(RegisterParseError::NotPrime("4".to_string()))
+        );
+        assert_eq!(
+            Registers::from_str("{2:1, 2:3}"),
+            Err(RegisterParseError::RepeatedPrime(2))
+        );
+        assert_eq!(
+            Registers::from_str("{2}"),
+            Err(RegisterParseError::BadEntry("2".to_string()))
+        );
+        assert_eq!(
+            Registers::from_str("2^-1"),
+            Err(RegisterParseError::BadExponent("-1".to_string()))
+        );
+        assert_eq!(
+            split_list("{2:1, 3:1}, 5").collect_vec(),
+            vec!["{2:1, 3:1}", " 5"]
+        );
+    }
+
+    #[test]
+    fn display_modes() {
+        let value = Registers::from(vec![(2, 10), (3, 5), (7, 1)]);
+        let shown = |value: &Registers, mode| value.display(mode).to_string();
+        assert_eq!(shown(&value, DisplayMode::Decimal), "1741824");
+        assert_eq!(shown(&value, DisplayMode::Factored), "2^10·3^5·7");
+        assert_eq!(shown(&value, DisplayMode::Exponents), "⟨10, 5, 0, 1⟩");
+        assert_eq!(shown(&Registers::ONE, DisplayMode::Factored), "1");
+        assert_eq!(shown(&Registers::ONE, DisplayMode::Exponents), "⟨⟩");
+        assert_eq!(
+            shown(
+                &Registers::from(vec![(2, 1), (101, 2)]),
+                DisplayMode::Exponents
+            ),
+            "{2:1, 101:2}"
+        );
+
+        let long = Registers::from(vec![(2, 100)]);
+        assert_eq!(
+            shown(&long, DisplayMode::Decimal),
+            "1.26765…e30 (about 31 digits)"
+        );
+        assert_eq!(long.to_string(), "1267650600228229401496703205376");
+        assert_eq!(
+            shown(&Registers::from(vec![(3, 1_000_000)]), DisplayMode::Decimal),
+            "1.79771…e477121 (about 477122 digits)"
+        );
+    }
+
+    #[test]
+    fn huge_exponents() {
+        let max = Registers::from(vec![(2, u64::MAX), (3, 1)]);
+        let doubled = &max * &max;
+        assert_eq!(
+            doubled.exponent(2),
+            Exponent::Big(BigUint::from(u64::MAX) * 2u32),
+            "promoted instead of overflowing"
+        );
+        assert!(doubled.exponent(2) > max.exponent(2));
+        assert_eq!(doubled.to_u64(), None);
+        assert_eq!(doubled.to_string(), "2^36893488147419103230·3^2");
+        assert_eq!(doubled.try_div(&max), Some(max.clone()), "demoted again");
+        assert_eq!(max.pow(2), doubled);
+        assert_eq!(max.try_div(&doubled), None);
+    }
 }
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use glam::{IVec2, UVec2};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    l3x::{L3XCommand, L3X},
+    registers::Registers,
+    traveler::Traveler,
+};
+
+/// The limits from the original problem statement, which are only enforced under
+/// [Ruleset::Competition]
+pub const MAX_MULTIPLIER: u64 = 30;
+pub const MAX_GRID_SIZE: u32 = 100;
+pub const MAX_STEPS: usize = 20000;
+pub const MAX_ACTIVE_TRAVELERS: usize = 10;
+
+/// Which limits a program is held to. By default, the IDE lifts every limit that the spec imposes,
+/// but the competition ruleset puts them back in place.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ruleset {
+    #[default]
+    Relaxed,
+    Competition,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum RuleViolation {
+    #[error("The multiplier at {location} is {value}, but must be between 1 and {MAX_MULTIPLIER}")]
+    MultiplierOutOfRange { location: IVec2, value: Registers },
+    #[error(
+        "The grid is {}x{}, but can be at most {MAX_GRID_SIZE}x{MAX_GRID_SIZE}",
+        .0.x, .0.y
+    )]
+    GridTooLarge(UVec2),
+    #[error("The program did not halt within {MAX_STEPS} steps")]
+    TooManySteps,
+    #[error(
+        "{} numbers were active at once, but at most {MAX_ACTIVE_TRAVELERS} are allowed",
+        .0.len()
+    )]
+    TooManyTravelers(Vec<Traveler>),
+}
+
+impl RuleViolation {
+    /// The cells on the grid which are responsible for this violation
+    pub fn cells(&self) -> Vec<IVec2> {
+        match self {
+            RuleViolation::MultiplierOutOfRange { location, .. } => vec![*location],
+            RuleViolation::TooManyTravelers(travelers) => {
+                travelers.iter().map(|t| t.location).unique().collect()
+            }
+            RuleViolation::GridTooLarge(_) | RuleViolation::TooManySteps => vec![],
+        }
+    }
+}
+
+impl Ruleset {
+    /// Whether two numbers entering a queue on the same tick always count as a collision
+    pub fn strict_queues(&self) -> bool {
+        matches!(self, Ruleset::Competition)
+    }
+
+    /// Whether a program has to halt within [MAX_STEPS] steps
+    pub fn limits_steps(&self) -> bool {
+        matches!(self, Ruleset::Competition)
+    }
+
+    /// Lists everything about the program itself which breaks this ruleset, ordered by location
+    pub fn check_program(
+        &self,
+        instructions: &HashMap<IVec2, L3X>,
+        dims: UVec2,
+    ) -> Vec<RuleViolation> {
+        if *self == Ruleset::Relaxed {
+            return vec![];
+        }
+
+        let mut violations = vec![];
+        if dims.cmpgt(UVec2::splat(MAX_GRID_SIZE)).any() {
+            violations.push(RuleViolation::GridTooLarge(dims));
+        }
+        violations.extend(
+            instructions
+                .iter()
+                .filter(|(location, _)| location.cmplt(dims.as_ivec2()).all())
+                .filter_map(|(&location, l3x)| match l3x.command {
+                    L3XCommand::Multiply(ref value)
+                        if !matches!(value.to_u64(), Some(1..=MAX_MULTIPLIER)) =>
+                    {
+                        Some((location, value.clone()))
+                    }
+                    _ => None,
+                })
+                .sorted_by_key(|(location, _)| (location.y, location.x))
+                .map(|(location, value)| RuleViolation::MultiplierOutOfRange { location, value }),
+        );
+        violations
+    }
+
+    /// Checks the limits which apply while the program is running, given the travelers which are
+    /// not being held in a queue
+    pub fn check_running<'a>(
+        &self,
+        ticks: usize,
+        active: impl Iterator<Item = &'a Traveler>,
+    ) -> Result<(), RuleViolation> {
+        if *self == Ruleset::Relaxed {
+            return Ok(());
+        }
+        let active = active.collect_vec();
+        if ticks >= MAX_STEPS {
+            Err(RuleViolation::TooManySteps)
+        } else if active.len() > MAX_ACTIVE_TRAVELERS {
+            Err(RuleViolation::TooManyTravelers(
+                active.into_iter().cloned().collect(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{ivec2, uvec2};
+
+    use super::*;
+
+    #[test]
+    fn multipliers() {
+        let instructions = [
+            ("30D", ivec2(0, 0)),
+            ("31D", ivec2(1, 0)),
+            ("%D", ivec2(0, 1)),
+        ]
+        .into_iter()
+        .map(|(cell, location)| (location, L3X::try_from(cell).unwrap()))
+        .collect();
+        assert_eq!(
+            Ruleset::Relaxed.check_program(&instructions, uvec2(2, 2)),
+            vec![]
+        );
+        assert_eq!(
+            Ruleset::Competition.check_program(&instructions, uvec2(2, 2)),
+            vec![RuleViolation::MultiplierOutOfRange {
+                location: ivec2(1, 0),
+                value: Registers::from(vec![(31, 1)])
+            }]
+        );
+    }
+
+    #[test]
+    fn grid_size() {
+        assert_eq!(
+            Ruleset::Competition.check_program(&HashMap::new(), uvec2(100, 101)),
+            vec![RuleViolation::GridTooLarge(uvec2(100, 101))]
+        );
+    }
+}
@@ -0,0 +1,1075 @@
+use std::collections::{HashMap, VecDeque};
+
+use glam::{ivec2, uvec2, IVec2, UVec2};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use smallvec::{smallvec, SmallVec};
+use vec_drain_where::VecDrainWhereExt;
+
+mod acceleration;
+mod cycle;
+use cycle::LoopCheckpoint;
+
+use crate::{
+    l3x::{Direction, L3XCommand, L3X},
+    registers::Registers,
+    ruleset::{RuleViolation, Ruleset},
+    swapbuffer::SwapBuffer,
+    trace::{Trace, TraceEvent},
+    traveler::Traveler,
+};
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatrixMode {
+    #[default]
+    L3,
+    L3X,
+}
+
+impl MatrixMode {
+    /// Guesses the mode of a program from the extension of the file it was stored in
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "l3" => Some(MatrixMode::L3),
+            "l3x" => Some(MatrixMode::L3X),
+            _ => None,
+        }
+    }
+
+    pub fn minimum_size(&self) -> UVec2 {
+        match self {
+            MatrixMode::L3 => uvec2(1, 1),
+            MatrixMode::L3X => uvec2(2, 2),
+        }
+    }
+}
+
+/// How a queue *&* treats two numbers arriving on the same tick
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueSemantics {
+    /// As in the spec: two numbers on a queue always collide, and popping from an empty queue is
+    /// an error
+    Strict,
+    /// A number entering the queue and one popping from it may arrive together. A number popping
+    /// from an empty queue waits there until the next number is pushed.
+    #[default]
+    Relaxed,
+}
+
+bitflags::bitflags! {
+    #[derive(Copy, Clone)]
+    struct Alignments: u8 {
+        const ALIGNED = 0b01;
+        const UNALIGNED = 0b10;
+    }
+}
+
+impl Alignments {
+    fn aligned(one: Direction, the_other: Direction) -> Self {
+        if one == the_other {
+            Self::ALIGNED
+        } else {
+            Self::UNALIGNED
+        }
+    }
+}
+
+/// Any of the ways in which a simulation can fail. Each of these carries the locations (and
+/// travelers, where relevant) responsible, so that they can be pointed out to the user.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum SimulationError {
+    #[error("In L3X mode, the square at (1, 0) must be a queue")]
+    NoInputQueue,
+    #[error("A traveler moved onto the empty cell at {}", .0.location)]
+    EmptyCell(Traveler),
+    #[error("A traveler left the grid at {}, which is not an output", .0.location)]
+    OutOfBounds(Traveler),
+    #[error("Travelers collided at {}", .cells.iter().join(", "))]
+    Collision {
+        cells: Vec<IVec2>,
+        travelers: Vec<Traveler>,
+    },
+    #[error("A traveler popped from the empty queue at {}", .0.location)]
+    EmptyQueue(Traveler),
+    #[error("A second traveler reached the single output at {}", .0.location)]
+    DuplicateOutput(Traveler),
+    #[error(
+        "The program halted while travelers were still waiting on the empty queues at {}",
+        .cells.iter().join(", ")
+    )]
+    EmptyQueueAtEnd {
+        cells: Vec<IVec2>,
+        travelers: Vec<Traveler>,
+    },
+    #[error("The program repeats itself every {period} ticks, at the latest from tick {start}")]
+    Loop {
+        period: usize,
+        /// A tick at which the program was already looping, which may be later than the first
+        start: usize,
+        cells: Vec<IVec2>,
+        travelers: Vec<Traveler>,
+    },
+    #[error(transparent)]
+    Rule(#[from] RuleViolation),
+}
+
+impl SimulationError {
+    /// The cells on the grid which are responsible for this error
+    pub fn cells(&self) -> Vec<IVec2> {
+        match self {
+            SimulationError::NoInputQueue => vec![ivec2(1, 0)],
+            SimulationError::Collision { cells, .. }
+            | SimulationError::EmptyQueueAtEnd { cells, .. }
+            | SimulationError::Loop { cells, .. } => cells.clone(),
+            SimulationError::Rule(violation) => violation.cells(),
+            _ => self.travelers().iter().map(|t| t.location).collect(),
+        }
+    }
+
+    /// The travelers which are responsible for this error, as they were when it was raised
+    pub fn travelers(&self) -> &[Traveler] {
+        match self {
+            SimulationError::NoInputQueue => &[],
+            SimulationError::Rule(RuleViolation::TooManyTravelers(travelers)) => travelers,
+            SimulationError::Rule(_) => &[],
+            SimulationError::EmptyCell(traveler)
+            | SimulationError::EmptyQueue(traveler)
+            | SimulationError::OutOfBounds(traveler)
+            | SimulationError::DuplicateOutput(traveler) => std::slice::from_ref(traveler),
+            SimulationError::Collision { travelers, .. }
+            | SimulationError::EmptyQueueAtEnd { travelers, .. }
+            | SimulationError::Loop { travelers, .. } => travelers,
+        }
+    }
+}
+
+/// How a single cell has been used over the course of a simulation
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CellStats {
+    /// The number of times a traveler has entered the cell
+    pub visits: usize,
+    /// The number of travelers which left through the cell's major output. Duplicating cells send
+    /// a traveler out of both outputs, so their exits add up to twice their visits.
+    pub major_exits: usize,
+    /// The number of travelers which left through the minor output, bouncing back the way they
+    /// came
+    pub minor_exits: usize,
+}
+
+/// Everything about a running simulation which changes from tick to tick
+#[derive(Clone)]
+pub struct Snapshot {
+    ticks: usize,
+    travelers: Vec<Traveler>,
+    queues: HashMap<IVec2, VecDeque<Registers>>,
+    waiting_for_queue: Vec<(Traveler, Registers)>,
+    output: Option<Registers>,
+    output_stream: Vec<Registers>,
+    cell_stats: HashMap<IVec2, CellStats>,
+}
+
+impl Snapshot {
+    pub fn ticks(&self) -> usize {
+        self.ticks
+    }
+}
+
+/// Runs an L3/L3X program without any dependence on the window or the ui. The program itself
+/// (`mode`, `instructions`, `dims`, and `ruleset`) is public so that front-ends can edit it in
+/// place, while the state of a running simulation is only reachable through the accessors.
+pub struct Simulator {
+    pub mode: MatrixMode,
+    pub instructions: HashMap<IVec2, L3X>,
+    pub dims: UVec2,
+    pub ruleset: Ruleset,
+    pub queue_semantics: QueueSemantics,
+    /// Whether every tick of the simulation is recorded into its [Trace]
+    pub tracing: bool,
+    /// Whether loops which multiply a lone traveler by the same number on every pass are jumped
+    /// over instead of being stepped through
+    pub skip_loops: bool,
+    /// Loops are only jumped over as far as this tick, so that a tick inside one can be stepped to
+    pub skip_until: usize,
+    /// Whether the state is checked against breakpoints after every tick, in which case no tick
+    /// may be jumped over
+    pub checking_breakpoints: bool,
+
+    ticks: usize,
+    queues: HashMap<IVec2, VecDeque<Registers>>,
+    waiting_for_queue: Vec<(Traveler, Registers)>,
+    travelers: SwapBuffer<Traveler>,
+    output: Option<Registers>,
+    output_stream: Vec<Registers>,
+    error: Option<SimulationError>,
+    trace: Trace,
+    cell_stats: HashMap<IVec2, CellStats>,
+    /// An earlier state of the simulation, which it is compared against to spot it looping
+    loop_checkpoint: Option<LoopCheckpoint>,
+    /// The last tick at which the only traveler entered each square in each direction
+    loop_visits: HashMap<(IVec2, Direction), usize>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            instructions: Default::default(),
+            dims: uvec2(1, 1),
+            ruleset: Default::default(),
+            queue_semantics: Default::default(),
+            tracing: false,
+            skip_loops: true,
+            skip_until: usize::MAX,
+            checking_breakpoints: false,
+            ticks: 0,
+            queues: Default::default(),
+            waiting_for_queue: Default::default(),
+            travelers: Default::default(),
+            output: Default::default(),
+            output_stream: Default::default(),
+            error: None,
+            trace: Default::default(),
+            cell_stats: Default::default(),
+            loop_checkpoint: None,
+            loop_visits: Default::default(),
+        }
+    }
+}
+
+impl Simulator {
+    pub fn new(mode: MatrixMode, instructions: HashMap<IVec2, L3X>, dims: UVec2) -> Self {
+        Self {
+            mode,
+            instructions,
+            dims,
+            ..Default::default()
+        }
+    }
+
+    /// A simulator for the same program under the same rules, which has not been started
+    pub fn program_copy(&self) -> Self {
+        Self {
+            mode: self.mode,
+            instructions: self.instructions.clone(),
+            dims: self.dims,
+            ruleset: self.ruleset,
+            queue_semantics: self.queue_semantics,
+            skip_loops: self.skip_loops,
+            ..Default::default()
+        }
+    }
+
+    pub fn travelers(&self) -> &[Traveler] {
+        &self.travelers
+    }
+
+    pub fn queues(&self) -> &HashMap<IVec2, VecDeque<Registers>> {
+        &self.queues
+    }
+
+    pub fn output(&self) -> Option<&Registers> {
+        self.output.as_ref()
+    }
+
+    pub fn output_stream(&self) -> &[Registers] {
+        &self.output_stream
+    }
+
+    /// Everything recorded about this simulation so far, if tracing was enabled when it started
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// How often each cell has been used since the simulation started
+    pub fn cell_stats(&self) -> &HashMap<IVec2, CellStats> {
+        &self.cell_stats
+    }
+
+    /// The number of ticks which have been simulated since the simulation was started
+    pub fn ticks(&self) -> usize {
+        self.ticks
+    }
+
+    /// The travelers which are sitting on a cell marked as a watch point
+    pub fn watched_travelers(&self) -> impl Iterator<Item = &Traveler> {
+        self.travelers.iter().filter(|traveler| {
+            self.instructions
+                .get(&traveler.location)
+                .map(|l3x| l3x.watch)
+                .unwrap_or(false)
+        })
+    }
+
+    /// The program has halted once there are no more travelers moving around the grid
+    pub fn is_halted(&self) -> bool {
+        self.travelers.is_empty()
+    }
+
+    /// The error which stopped this simulation, if there was one
+    pub fn error(&self) -> Option<&SimulationError> {
+        self.error.as_ref()
+    }
+
+    /// Everything about the program which breaks the current ruleset
+    pub fn rule_violations(&self) -> Vec<RuleViolation> {
+        self.ruleset.check_program(&self.instructions, self.dims)
+    }
+
+    /// Places the input traveler (and the input stream, in L3X mode) onto the grid. Fails if the
+    /// program is in L3X mode but the input stream square is not a queue, or if the program breaks
+    /// the ruleset.
+    pub fn start(
+        &mut self,
+        input: Registers,
+        stream: Vec<Registers>,
+    ) -> Result<(), SimulationError> {
+        self.reset();
+        if self.mode == MatrixMode::L3X
+            && self
+                .instructions
+                .get(&ivec2(1, 0))
+                .map(|l3x| l3x.command != L3XCommand::Queue)
+                .unwrap_or(true)
+        {
+            return Err(SimulationError::NoInputQueue);
+        }
+        if let Some(violation) = self.rule_violations().into_iter().next() {
+            return Err(violation.into());
+        }
+
+        let traveler = Traveler {
+            value: input,
+            location: IVec2::ZERO,
+            direction: Direction::Down,
+        };
+        if self.tracing {
+            self.trace.record(0, TraceEvent::Traveler(traveler.clone()));
+        }
+        self.travelers.push(traveler);
+        self.queues.insert(ivec2(1, 0), stream.into());
+
+        Ok(())
+    }
+
+    /// Removes all state from the current simulation, leaving the program intact
+    pub fn reset(&mut self) {
+        self.ticks = 0;
+        self.travelers.clear();
+        self.queues.clear();
+        self.waiting_for_queue.clear();
+        self.output = None;
+        self.output_stream.clear();
+        self.error = None;
+        self.trace.clear();
+        self.cell_stats.clear();
+        self.loop_checkpoint = None;
+        self.loop_visits.clear();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ticks: self.ticks,
+            travelers: self.travelers.to_vec(),
+            queues: self.queues.clone(),
+            waiting_for_queue: self.waiting_for_queue.clone(),
+            output: self.output.clone(),
+            output_stream: self.output_stream.clone(),
+            cell_stats: self.cell_stats.clone(),
+        }
+    }
+
+    /// Puts the simulation back into the state it was in when the snapshot was taken. Any error
+    /// the simulation had stopped with is forgotten, so that it can resume from there.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let mut trace = std::mem::take(&mut self.trace);
+        trace.truncate(snapshot.ticks);
+        self.reset();
+        self.trace = trace;
+        self.ticks = snapshot.ticks;
+        self.travelers.extend(snapshot.travelers.iter().cloned());
+        self.queues = snapshot.queues.clone();
+        self.waiting_for_queue = snapshot.waiting_for_queue.clone();
+        self.output = snapshot.output.clone();
+        self.output_stream = snapshot.output_stream.clone();
+        self.cell_stats = snapshot.cell_stats.clone();
+    }
+
+    /// Advances the simulation by one tick. If the travelers have collided or any of them cannot
+    /// move, the simulation stops and keeps returning the same error until it is reset.
+    pub fn step(&mut self) -> Result<(), SimulationError> {
+        if let Some(ref error) = self.error {
+            return Err(error.clone());
+        }
+
+        let result = self
+            .ruleset
+            .check_running(
+                self.ticks,
+                self.travelers.iter().filter(|t| !self.enters_queue(t)),
+            )
+            .map_err(SimulationError::from)
+            .and_then(|()| self.collision_free())
+            .and_then(|()| self.check_moves())
+            .and_then(|()| {
+                self.skip_loop();
+                self.step_travelers();
+                self.check_queues_at_halt()
+            })
+            .and_then(|()| self.check_loop());
+        if let Err(ref error) = result {
+            self.error = Some(error.clone());
+        }
+        result
+    }
+
+    /// Steps until the program halts. Returns `Ok(false)` if it was still running after
+    /// `max_ticks` ticks.
+    pub fn run(&mut self, max_ticks: usize) -> Result<bool, SimulationError> {
+        while !self.is_halted() {
+            if self.ticks >= max_ticks {
+                return Ok(false);
+            }
+            self.step()?;
+        }
+        Ok(true)
+    }
+
+    pub fn is_output_cell(&self, location: IVec2) -> bool {
+        location == self.dims.as_ivec2() - ivec2(1, 0)
+            || self.mode == MatrixMode::L3X && location == self.dims.as_ivec2() - ivec2(2, 0)
+    }
+
+    /// The competition ruleset always follows the spec for queues, whatever the program asks for
+    pub fn queue_semantics(&self) -> QueueSemantics {
+        if self.ruleset.strict_queues() {
+            QueueSemantics::Strict
+        } else {
+            self.queue_semantics
+        }
+    }
+
+    /// Iterates through the travelers stored in this simulation and checks whether they collide.
+    /// Under relaxed queue semantics, ignores collisions on a queue *&&* between a traveler aligned
+    /// and one not aligned with the queue.
+    pub fn collision_free(&self) -> Result<(), SimulationError> {
+        let mut cells = vec![];
+        let mut travelers = vec![];
+        let occupants = self
+            .travelers
+            .iter()
+            .into_group_map_by(|traveler| traveler.location)
+            .into_iter()
+            .filter(|(_, occupants)| occupants.len() > 1)
+            .sorted_by_key(|(location, _)| (location.y, location.x));
+        for (location, occupants) in occupants {
+            let collides = match self.instructions.get(&location) {
+                Some(l3x)
+                    if l3x.command == L3XCommand::Queue
+                        && self.queue_semantics() == QueueSemantics::Relaxed =>
+                {
+                    let mut alignments = Alignments::empty();
+                    occupants.iter().any(|traveler| {
+                        let aligned = Alignments::aligned(l3x.direction, traveler.direction);
+                        let collides = alignments.contains(aligned);
+                        alignments |= aligned;
+                        collides
+                    })
+                }
+                Some(_) => true,
+                // travelers off the grid or on empty cells are caught by `check_moves`
+                None => false,
+            };
+            if collides {
+                cells.push(location);
+                travelers.extend(occupants.into_iter().cloned());
+            }
+        }
+
+        if cells.is_empty() {
+            Ok(())
+        } else {
+            Err(SimulationError::Collision { cells, travelers })
+        }
+    }
+
+    /// Makes sure that every traveler is either on an instruction or on an output, that the
+    /// single output is not given twice, and under strict queue semantics, that no traveler pops
+    /// from an empty queue
+    fn check_moves(&self) -> Result<(), SimulationError> {
+        let single_output = self.dims.as_ivec2() - ivec2(1, 0);
+        let mut output_taken = self.output.is_some();
+        let strict_queues = self.queue_semantics() == QueueSemantics::Strict;
+        for traveler in self.travelers.iter() {
+            let location = traveler.location;
+            if location.cmpge(IVec2::ZERO).all() && location.cmplt(self.dims.as_ivec2()).all() {
+                if !self.instructions.contains_key(&location) {
+                    return Err(SimulationError::EmptyCell(traveler.clone()));
+                }
+                if strict_queues && self.pops_empty_queue(traveler) {
+                    return Err(SimulationError::EmptyQueue(traveler.clone()));
+                }
+            } else if location == single_output {
+                if output_taken {
+                    return Err(SimulationError::DuplicateOutput(traveler.clone()));
+                }
+                output_taken = true;
+            } else if !self.is_output_cell(location) {
+                return Err(SimulationError::OutOfBounds(traveler.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a traveler is pushed into a queue this tick, or is popped by a traveler waiting on
+    /// an empty queue, so that it is held rather than active
+    fn enters_queue(&self, traveler: &Traveler) -> bool {
+        let pushes = self
+            .instructions
+            .get(&traveler.location)
+            .is_some_and(|l3x| {
+                l3x.command == L3XCommand::Queue && l3x.direction == traveler.direction
+            });
+        pushes
+            || self
+                .waiting_for_queue
+                .iter()
+                .any(|(waiting, _)| waiting.location == traveler.location)
+    }
+
+    fn pops_empty_queue(&self, traveler: &Traveler) -> bool {
+        let pops = self
+            .instructions
+            .get(&traveler.location)
+            .map(|l3x| l3x.command == L3XCommand::Queue && l3x.direction != traveler.direction)
+            .unwrap_or(false);
+        let empty = self
+            .queues
+            .get(&traveler.location)
+            .map(|queue| queue.is_empty())
+            .unwrap_or(true);
+        pops && empty
+    }
+
+    /// A program which halts while travelers are still waiting for a queue has popped from an
+    /// empty queue
+    fn check_queues_at_halt(&self) -> Result<(), SimulationError> {
+        if self.is_halted() && !self.waiting_for_queue.is_empty() {
+            let travelers = self
+                .waiting_for_queue
+                .iter()
+                .map(|(traveler, _)| traveler.clone())
+                .collect_vec();
+            Err(SimulationError::EmptyQueueAtEnd {
+                cells: travelers.iter().map(|t| t.location).unique().collect(),
+                travelers,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moves every traveler by one tick. The travelers must have been checked with
+    /// `collision_free` and `check_moves` beforehand.
+    fn step_travelers(&mut self) {
+        let dims = self.dims.as_ivec2();
+        let tick = self.ticks + 1;
+        let tracing = self.tracing;
+        let trace = &mut self.trace;
+        let cell_stats = &mut self.cell_stats;
+        self.travelers.swap(|mut traveler| {
+            if traveler.location == dims - ivec2(1, 0) {
+                if tracing {
+                    trace.record(tick, TraceEvent::Output(traveler.value.clone()));
+                }
+                self.output = Some(traveler.value);
+                return smallvec![];
+            } else if traveler.location == dims - ivec2(2, 0) {
+                if tracing {
+                    trace.record(tick, TraceEvent::StreamOutput(traveler.value.clone()));
+                }
+                self.output_stream.push(traveler.value);
+                return smallvec![];
+            }
+            let location = traveler.location;
+            let instruction = &self.instructions[&location];
+
+            let aligned = traveler.direction == instruction.direction;
+
+            let out: SmallVec<[_; 2]> = match &instruction.command {
+                L3XCommand::Multiply(with) => {
+                    smallvec![if aligned {
+                        traveler.mul(with).direct(instruction.direction)
+                    } else if let Some(div) = traveler.value.try_div(with) {
+                        traveler.value(div).direct(instruction.direction)
+                    } else {
+                        traveler.direct(instruction.direction.opposite())
+                    }]
+                }
+                L3XCommand::Duplicate => {
+                    smallvec![
+                        traveler.clone().direct(instruction.direction),
+                        traveler.direct(instruction.direction.opposite())
+                    ]
+                }
+                L3XCommand::Queue => {
+                    if aligned {
+                        if tracing {
+                            trace.record(
+                                tick,
+                                TraceEvent::QueuePush {
+                                    location: traveler.location,
+                                    value: traveler.value.clone(),
+                                },
+                            );
+                        }
+                        self.queues
+                            .entry(traveler.location)
+                            .and_modify(|q| q.push_back(traveler.value.clone()))
+                            .or_insert_with(|| vec![traveler.value.clone()].into());
+                        smallvec![]
+                    } else {
+                        traveler.direction = instruction.direction;
+                        if let Some(queued) = self
+                            .queues
+                            .get_mut(&traveler.location)
+                            .and_then(|q| q.pop_front())
+                        {
+                            if tracing {
+                                trace.record(
+                                    tick,
+                                    TraceEvent::QueuePop {
+                                        location: traveler.location,
+                                        value: queued.clone(),
+                                    },
+                                );
+                            }
+                            smallvec![traveler.mul(&queued).step()]
+                        } else {
+                            self.waiting_for_queue.push((traveler, Registers::ONE));
+                            smallvec![]
+                        }
+                    }
+                }
+                L3XCommand::Annihilate => {
+                    smallvec![traveler.value(Registers::ONE).direct(instruction.direction)]
+                }
+            };
+
+            let stats = cell_stats.entry(location).or_default();
+            stats.visits += 1;
+            for t in &out {
+                if t.direction == instruction.direction {
+                    stats.major_exits += 1;
+                } else {
+                    stats.minor_exits += 1;
+                }
+            }
+            out
+        });
+
+        let dequeued_travelers = self
+            .waiting_for_queue
+            .e_drain_where(|(traveler, u)| {
+                let queued_traveler = self
+                    .travelers
+                    .iter()
+                    .position(|e| e.location == traveler.location)
+                    .map(|ix| self.travelers.swap_remove(ix).value);
+                if let (true, Some(value)) = (tracing, &queued_traveler) {
+                    trace.record(
+                        tick,
+                        TraceEvent::QueuePop {
+                            location: traveler.location,
+                            value: value.clone(),
+                        },
+                    );
+                }
+
+                queued_traveler.map(|register| *u = register).is_some()
+            })
+            .map(|(traveler, multiplier)| {
+                cell_stats.entry(traveler.location).or_default().major_exits += 1;
+                traveler.mul(&multiplier).step()
+            })
+            .collect_vec();
+
+        self.travelers.extend(dequeued_travelers);
+        self.ticks += 1;
+        if tracing {
+            for traveler in self.travelers.iter() {
+                trace.record(tick, TraceEvent::Traveler(traveler.clone()));
+            }
+        }
+    }
+}
+
+/// Programs shared by the tests of several modules
+#[cfg(test)]
+pub mod test_programs {
+    use super::*;
+
+    /// Lays out a program from rows of cells, leaving cells which do not parse empty
+    pub fn program(mode: MatrixMode, rows: &[&[&str]]) -> Simulator {
+        let instructions = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter().enumerate().filter_map(move |(x, cell)| {
+                    L3X::try_from(*cell)
+                        .ok()
+                        .map(|l3x| (ivec2(x as i32, y as i32), l3x))
+                })
+            })
+            .collect();
+        let dims = uvec2(rows[0].len() as u32, rows.len() as u32);
+        Simulator::new(mode, instructions, dims)
+    }
+
+    /// Turns 2^n into 3^n
+    pub fn transfer() -> Simulator {
+        program(
+            MatrixMode::L3,
+            &[&["1D", "1L"], &["1D", "3U"], &["1R", "2U"]],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_programs::{program, transfer};
+    use super::*;
+
+    fn run(sim: &mut Simulator) {
+        while !sim.is_halted() {
+            sim.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn clear_register() {
+        let mut sim = program(MatrixMode::L3, &[&["1R", "2L", "1D"]]);
+        sim.start(Registers::from(vec![(2, 5)]), vec![]).unwrap();
+        run(&mut sim);
+        assert_eq!(sim.output(), Some(&Registers::ONE));
+    }
+
+    #[test]
+    fn transfer_register() {
+        let mut sim = transfer();
+        sim.start(Registers::from(vec![(2, 4)]), vec![]).unwrap();
+        run(&mut sim);
+        assert_eq!(sim.output(), Some(&Registers::from(vec![(3, 4)])));
+    }
+
+    #[test]
+    fn stream_passthrough() {
+        let mut sim = program(
+            MatrixMode::L3X,
+            &[
+                &["~E", "&S", "1E", "1S"],
+                &["1S", "%W", "~N", "2S"],
+                &["1E", "1E", "1S", "1S"],
+            ],
+        );
+        sim.start(
+            Registers::from(vec![(2, 1)]),
+            vec![Registers::from(vec![(2, 3)])],
+        )
+        .unwrap();
+        run(&mut sim);
+        assert_eq!(sim.output(), Some(&Registers::from(vec![(2, 1)])));
+        assert_eq!(sim.output_stream(), &[Registers::from(vec![(2, 3)])]);
+    }
+
+    #[test]
+    fn trace_queues_and_outputs() {
+        let mut sim = program(
+            MatrixMode::L3X,
+            &[
+                &["~E", "&S", "1E", "1S"],
+                &["1S", "%W", "~N", "2S"],
+                &["1E", "1E", "1S", "1S"],
+            ],
+        );
+        sim.tracing = true;
+        sim.start(
+            Registers::from(vec![(2, 1)]),
+            vec![Registers::from(vec![(2, 3)])],
+        )
+        .unwrap();
+        run(&mut sim);
+
+        let events = sim.trace().events();
+        assert!(matches!(events[0], (0, TraceEvent::Traveler(_))));
+        let others = events
+            .iter()
+            .filter(|(_, event)| !matches!(event, TraceEvent::Traveler(_)))
+            .map(|(_, event)| event.clone())
+            .collect_vec();
+        assert_eq!(
+            others,
+            vec![
+                TraceEvent::QueuePop {
+                    location: ivec2(1, 0),
+                    value: Registers::from(vec![(2, 3)])
+                },
+                TraceEvent::StreamOutput(Registers::from(vec![(2, 3)])),
+                TraceEvent::Output(Registers::from(vec![(2, 1)])),
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut sim = transfer();
+        sim.start(Registers::from(vec![(2, 4)]), vec![]).unwrap();
+        (0..5).for_each(|_| sim.step().unwrap());
+        let snapshot = sim.snapshot();
+        run(&mut sim);
+        let ticks = sim.ticks();
+
+        sim.restore(&snapshot);
+        assert_eq!(sim.ticks(), 5);
+        assert_eq!(sim.output(), None);
+        run(&mut sim);
+        assert_eq!(sim.ticks(), ticks);
+        assert_eq!(sim.output(), Some(&Registers::from(vec![(3, 4)])));
+    }
+
+    #[test]
+    fn l3x_requires_input_queue() {
+        let mut sim = program(MatrixMode::L3X, &[&["1D", "1D"], &["1D", "1D"]]);
+        assert_eq!(
+            sim.start(Registers::ONE, vec![]),
+            Err(SimulationError::NoInputQueue)
+        );
+    }
+
+    #[test]
+    fn errors_stop_the_simulation() {
+        let mut sim = program(MatrixMode::L3, &[&["1R", "", "1D"]]);
+        sim.start(Registers::ONE, vec![]).unwrap();
+        sim.step().unwrap();
+        let error = sim.step().unwrap_err();
+        assert!(matches!(error, SimulationError::EmptyCell(_)));
+        assert_eq!(error.cells(), vec![ivec2(1, 0)]);
+        assert_eq!(sim.step(), Err(error), "the simulation should stay stopped");
+
+        let mut sim = program(MatrixMode::L3, &[&["1L"]]);
+        sim.start(Registers::ONE, vec![]).unwrap();
+        sim.step().unwrap();
+        assert!(matches!(sim.step(), Err(SimulationError::OutOfBounds(_))));
+    }
+
+    #[test]
+    fn competition_limits() {
+        // doubles forever, so that the state never repeats
+        let mut sim = program(MatrixMode::L3, &[&["1R", "2R", "1D"], &["1U", "1L", "1L"]]);
+        sim.ruleset = Ruleset::Competition;
+        sim.start(Registers::ONE, vec![]).unwrap();
+        let error = loop {
+            if let Err(e) = sim.step() {
+                break e;
+            }
+        };
+        assert_eq!(error, SimulationError::Rule(RuleViolation::TooManySteps));
+        assert_eq!(sim.ticks(), crate::ruleset::MAX_STEPS);
+
+        // eleven numbers side by side, the last of which is pushed into a queue
+        let row = ["1D"; 10].into_iter().chain(["&D"]).collect_vec();
+        let mut sim = program(MatrixMode::L3, &[&row, &["1D"; 11], &["1D"; 11]]);
+        sim.ruleset = Ruleset::Competition;
+        sim.start(Registers::ONE, vec![]).unwrap();
+        sim.travelers.extend((1..11).map(|x| Traveler {
+            value: Registers::ONE,
+            location: ivec2(x, 0),
+            direction: Direction::Down,
+        }));
+        sim.step().unwrap();
+        assert_eq!(sim.travelers().len(), 10);
+
+        let mut sim = program(MatrixMode::L3, &[&["1D"; 11], &["1D"; 11], &["1D"; 11]]);
+        sim.ruleset = Ruleset::Competition;
+        sim.start(Registers::ONE, vec![]).unwrap();
+        sim.travelers.extend((1..11).map(|x| Traveler {
+            value: Registers::ONE,
+            location: ivec2(x, 0),
+            direction: Direction::Down,
+        }));
+        assert!(matches!(
+            sim.step(),
+            Err(SimulationError::Rule(RuleViolation::TooManyTravelers(_)))
+        ));
+
+        let mut sim = program(MatrixMode::L3, &[&["31D"]]);
+        sim.ruleset = Ruleset::Competition;
+        assert!(matches!(
+            sim.start(Registers::ONE, vec![]),
+            Err(SimulationError::Rule(
+                RuleViolation::MultiplierOutOfRange { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn queue_semantics() {
+        // pops from the input stream, which is empty
+        let rows: &[&[&str]] = &[&["1R", "&D"], &["1D", "1D"]];
+
+        let mut sim = program(MatrixMode::L3X, rows);
+        sim.start(Registers::ONE, vec![]).unwrap();
+        assert!(matches!(
+            sim.run(100),
+            Err(SimulationError::EmptyQueueAtEnd { .. })
+        ));
+
+        let mut sim = program(MatrixMode::L3X, rows);
+        sim.queue_semantics = QueueSemantics::Strict;
+        sim.start(Registers::ONE, vec![]).unwrap();
+        assert_eq!(
+            sim.run(100),
+            Err(SimulationError::EmptyQueue(Traveler {
+                value: Registers::ONE,
+                location: ivec2(1, 0),
+                direction: Direction::Right,
+            }))
+        );
+        assert_eq!(sim.ticks(), 1);
+    }
+
+    #[test]
+    fn count_cell_usage() {
+        let mut sim = program(MatrixMode::L3, &[&["1R", "2L", "1D"]]);
+        sim.start(Registers::from(vec![(2, 3)]), vec![]).unwrap();
+        run(&mut sim);
+        assert_eq!(
+            sim.cell_stats()[&ivec2(1, 0)],
+            CellStats {
+                visits: 4,
+                major_exits: 3,
+                minor_exits: 1
+            }
+        );
+        assert_eq!(sim.cell_stats()[&ivec2(0, 0)].visits, 4);
+
+        let mut sim = program(MatrixMode::L3, &[&["1D"], &["%D"]]);
+        sim.start(Registers::ONE, vec![]).unwrap();
+        sim.step().unwrap();
+        sim.step().unwrap();
+        assert_eq!(
+            sim.cell_stats()[&ivec2(0, 1)],
+            CellStats {
+                visits: 1,
+                major_exits: 1,
+                minor_exits: 1
+            }
+        );
+    }
+
+    #[test]
+    fn skip_loops() {
+        let transfer = transfer();
+        let mut stepped = transfer.program_copy();
+        stepped.skip_loops = false;
+        stepped
+            .start(Registers::from(vec![(2, 50)]), vec![])
+            .unwrap();
+        run(&mut stepped);
+        let mut skipped = transfer.program_copy();
+        skipped
+            .start(Registers::from(vec![(2, 50)]), vec![])
+            .unwrap();
+        run(&mut skipped);
+        assert_eq!(skipped.output(), Some(&Registers::from(vec![(3, 50)])));
+        assert_eq!(skipped.output(), stepped.output());
+        assert_eq!(skipped.ticks(), stepped.ticks());
+        assert_eq!(skipped.cell_stats(), stepped.cell_stats());
+
+        let mut sim = transfer.program_copy();
+        sim.start(Registers::from(vec![(2, 1_000_000), (5, 1)]), vec![])
+            .unwrap();
+        let mut steps = 0;
+        while !sim.is_halted() {
+            sim.step().unwrap();
+            steps += 1;
+        }
+        assert!(steps < 100);
+        assert_eq!(sim.ticks(), 6_000_005);
+        assert_eq!(
+            sim.output(),
+            Some(&Registers::from(vec![(3, 1_000_000), (5, 1)]))
+        );
+
+        let mut checked = transfer.program_copy();
+        checked.checking_breakpoints = true;
+        checked
+            .start(Registers::from(vec![(2, 50)]), vec![])
+            .unwrap();
+        let mut steps = 0;
+        while !checked.is_halted() {
+            checked.step().unwrap();
+            steps += 1;
+        }
+        assert_eq!(steps, stepped.ticks());
+
+        let mut watched = transfer.program_copy();
+        watched.instructions.get_mut(&ivec2(0, 2)).unwrap().watch = true;
+        watched
+            .start(Registers::from(vec![(2, 50)]), vec![])
+            .unwrap();
+        let mut steps = 0;
+        while !watched.is_halted() {
+            watched.step().unwrap();
+            steps += 1;
+        }
+        assert_eq!(steps, stepped.ticks());
+    }
+
+    #[test]
+    fn detect_loops() {
+        let mut sim = program(MatrixMode::L3, &[&["1R", "1D"], &["1U", "1L"]]);
+        sim.start(Registers::ONE, vec![]).unwrap();
+        let error = loop {
+            if let Err(e) = sim.step() {
+                break e;
+            }
+        };
+        assert_eq!(sim.ticks(), 8);
+        assert_eq!(
+            error,
+            SimulationError::Loop {
+                period: 4,
+                start: 4,
+                cells: vec![ivec2(0, 0), ivec2(1, 0), ivec2(0, 1), ivec2(1, 1)],
+                travelers: vec![Traveler {
+                    value: Registers::ONE,
+                    location: ivec2(0, 0),
+                    direction: Direction::Up,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn collisions_carry_locations() {
+        let mut sim = program(
+            MatrixMode::L3,
+            &[&["1R", "1D", ""], &["1D", "%R", "1D"], &["1R", "1D", "1L"]],
+        );
+        sim.start(Registers::ONE, vec![]).unwrap();
+        let error = loop {
+            if let Err(e) = sim.step() {
+                break e;
+            }
+        };
+        match error {
+            SimulationError::Collision { cells, travelers } => {
+                assert_eq!(cells, vec![ivec2(1, 2)]);
+                assert_eq!(travelers.len(), 2);
+            }
+            e => panic!("expected a collision, got {e}"),
+        }
+    }
+}
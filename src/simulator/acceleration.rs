@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+
+use glam::IVec2;
+use itertools::Itertools;
+
+use super::Simulator;
+use crate::{
+    l3x::L3XCommand,
+    registers::{Exponent, Registers},
+    traveler::Traveler,
+};
+
+/// A divisibility check made on one pass around a loop
+struct Check {
+    /// Each prime of the divisor, with its power in the number and in the divisor
+    factors: Vec<(u64, i64, i64)>,
+    divided: bool,
+}
+
+/// One pass of a traveler around a loop, worked out without moving it
+struct Pass {
+    /// Each square passed through, and whether it was left through its major output
+    cells: Vec<(IVec2, bool)>,
+    checks: Vec<Check>,
+    /// How much the power of each prime changes over the pass
+    delta: BTreeMap<u64, i64>,
+}
+
+impl Pass {
+    /// How many passes in a row go the same way as this one, or `None` if they go on forever.
+    /// Since every pass changes the powers by the same amount, a check keeps going the same way
+    /// until the power it depends on has moved past the divisor.
+    fn repeats(&self) -> Option<i64> {
+        let change = |prime| self.delta.get(&prime).copied().unwrap_or(0);
+        self.checks
+            .iter()
+            .filter_map(|check| {
+                if check.divided {
+                    // divides again for as long as every power stays large enough
+                    check
+                        .factors
+                        .iter()
+                        .filter(|&&(prime, _, _)| change(prime) < 0)
+                        .map(|&(prime, power, divisor)| (power - divisor) / -change(prime))
+                        .min()
+                } else {
+                    // fails again for as long as any power which was too small stays that way
+                    check
+                        .factors
+                        .iter()
+                        .filter(|&&(_, power, divisor)| power < divisor)
+                        .map(|&(prime, power, divisor)| {
+                            (change(prime) > 0).then(|| (divisor - 1 - power) / change(prime))
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .and_then(|limits| limits.into_iter().max())
+                }
+            })
+            .min()
+            .map(|extra| extra + 1)
+    }
+}
+
+/// The power of each prime in a number, unless one is too large to work with
+fn powers(value: &Registers) -> Option<BTreeMap<u64, i64>> {
+    value
+        .0
+        .iter()
+        .map(|(prime, pow)| Some((*prime, i64::try_from(pow.to_u64()?).ok()?)))
+        .collect()
+}
+
+impl Simulator {
+    /// Follows the traveler for `period` ticks without moving it. Returns `None` unless it comes
+    /// back to where it started, having only been multiplied and divided along the way.
+    fn dry_run(&self, traveler: &Traveler, period: usize) -> Option<Pass> {
+        let start = powers(&traveler.value)?;
+        let mut powers = start.clone();
+        let (mut location, mut direction) = (traveler.location, traveler.direction);
+        let mut cells = Vec::with_capacity(period);
+        let mut checks = vec![];
+
+        for _ in 0..period {
+            let instruction = self.instructions.get(&location)?;
+            let L3XCommand::Multiply(ref with) = instruction.command else {
+                return None;
+            };
+            if instruction.watch {
+                return None;
+            }
+
+            let with = self::powers(with)?;
+            let exit = if direction == instruction.direction {
+                for (&prime, &pow) in &with {
+                    let power = powers.entry(prime).or_default();
+                    *power = power.checked_add(pow)?;
+                }
+                instruction.direction
+            } else {
+                let factors = with
+                    .into_iter()
+                    .map(|(prime, pow)| (prime, powers.get(&prime).copied().unwrap_or(0), pow))
+                    .collect_vec();
+                let divided = factors.iter().all(|&(_, power, divisor)| power >= divisor);
+                if divided {
+                    for &(prime, _, divisor) in &factors {
+                        *powers.entry(prime).or_default() -= divisor;
+                    }
+                }
+                checks.push(Check { factors, divided });
+                if divided {
+                    instruction.direction
+                } else {
+                    instruction.direction.opposite()
+                }
+            };
+            cells.push((location, exit == instruction.direction));
+            location += IVec2::from(exit);
+            direction = exit;
+        }
+
+        if location != traveler.location || direction != traveler.direction {
+            return None;
+        }
+        let delta = powers
+            .into_iter()
+            .map(|(prime, power)| (prime, power - start.get(&prime).copied().unwrap_or(0)))
+            .collect();
+        Some(Pass {
+            cells,
+            checks,
+            delta,
+        })
+    }
+
+    /// If the only traveler has come back to a square it entered the same way on an earlier
+    /// tick, and every pass around that loop multiplies it by the same number, jumps ahead to the
+    /// last pass which goes the same way, or the last one before [Simulator::skip_until]. The tick
+    /// counter and cell statistics are advanced as if every pass had been stepped through.
+    /// Nothing is skipped while breakpoints or watch points could stop the simulation on one of
+    /// the passes in between.
+    pub(super) fn skip_loop(&mut self) {
+        if !self.skip_loops
+            || self.checking_breakpoints
+            || self.tracing
+            || self.ruleset.limits_steps()
+            || self.travelers.len() != 1
+            || !self.waiting_for_queue.is_empty()
+        {
+            self.loop_visits.clear();
+            return;
+        }
+
+        let traveler = &self.travelers[0];
+        let Some(last) = self
+            .loop_visits
+            .insert((traveler.location, traveler.direction), self.ticks)
+        else {
+            return;
+        };
+        // whether or not this loop can be skipped, wait a whole pass before trying again
+        self.loop_visits.clear();
+        if self
+            .instructions
+            .values()
+            .any(|instruction| instruction.watch)
+        {
+            return;
+        }
+        let Some(pass) = self.dry_run(traveler, self.ticks - last) else {
+            return;
+        };
+        let room = self.skip_until.saturating_sub(self.ticks) / pass.cells.len();
+        let Some(passes) = pass
+            .repeats()
+            .map(|passes| passes.min(room.try_into().unwrap_or(i64::MAX)))
+            .filter(|&passes| passes > 1)
+        else {
+            return;
+        };
+
+        let Some(start) = powers(&traveler.value) else {
+            return;
+        };
+        let mut value = traveler.value.clone();
+        for (&prime, &delta) in &pass.delta {
+            let power = delta
+                .checked_mul(passes)
+                .and_then(|change| change.checked_add(start.get(&prime).copied().unwrap_or(0)))
+                .and_then(|power| u64::try_from(power).ok());
+            match power {
+                Some(power) => value.set_exponent(prime, Exponent::from(power)),
+                // too large to store, so let the simulation get there one tick at a time
+                None => return,
+            }
+        }
+
+        let passes = passes as usize;
+        self.travelers[0].value = value;
+        self.ticks += passes * pass.cells.len();
+        for (location, major) in pass.cells {
+            let stats = self.cell_stats.entry(location).or_default();
+            stats.visits += passes;
+            if major {
+                stats.major_exits += passes;
+            } else {
+                stats.minor_exits += passes;
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use glam::IVec2;
+use itertools::Itertools;
+
+use super::{SimulationError, Simulator};
+use crate::{registers::Registers, traveler::Traveler};
+
+/// Puts travelers into an order which does not depend on the order they were created in
+fn traveler_key(traveler: &Traveler) -> (i32, i32, u8, u64) {
+    let mut hasher = DefaultHasher::new();
+    traveler.value.hash(&mut hasher);
+    (
+        traveler.location.y,
+        traveler.location.x,
+        traveler.direction as u8,
+        hasher.finish(),
+    )
+}
+
+/// A copy of everything which decides how a simulation continues, as it was on one tick. Since
+/// the simulation is deterministic, coming back to this state means that it loops forever.
+///
+/// Only one checkpoint is kept at a time, and it is moved forward after twice as many ticks each
+/// time (Brent's cycle detection), so a loop is always found within a few periods of entering it,
+/// however long the simulation has run.
+pub(super) struct LoopCheckpoint {
+    tick: usize,
+    /// How many ticks the simulation is compared against this checkpoint before it is moved
+    length: usize,
+    travelers: Vec<Traveler>,
+    queues: Vec<(IVec2, VecDeque<Registers>)>,
+    waiting_for_queue: Vec<(Traveler, Registers)>,
+    output: Option<Registers>,
+    /// Outputs are only ever added to the stream, so two states with as many outputs have the same
+    /// stream
+    output_stream_len: usize,
+}
+
+impl LoopCheckpoint {
+    fn new(sim: &Simulator, length: usize) -> Self {
+        Self {
+            tick: sim.ticks,
+            length,
+            travelers: sim
+                .travelers
+                .iter()
+                .cloned()
+                .sorted_by_cached_key(traveler_key)
+                .collect(),
+            queues: sim
+                .queues
+                .iter()
+                .filter(|(_, queue)| !queue.is_empty())
+                .map(|(location, queue)| (*location, queue.clone()))
+                .sorted_by_key(|(location, _)| (location.y, location.x))
+                .collect(),
+            waiting_for_queue: sim
+                .waiting_for_queue
+                .iter()
+                .cloned()
+                .sorted_by_cached_key(|(traveler, _)| traveler_key(traveler))
+                .collect(),
+            output: sim.output.clone(),
+            output_stream_len: sim.output_stream.len(),
+        }
+    }
+
+    /// Whether the simulation is in exactly this state again. The cheap comparisons are made
+    /// first, so that the contents of the queues are only compared once everything else matches.
+    fn matches(&self, sim: &Simulator) -> bool {
+        let queues_match_in_length = || {
+            sim.queues
+                .values()
+                .filter(|queue| !queue.is_empty())
+                .count()
+                == self.queues.len()
+                && self.queues.iter().all(|(location, queue)| {
+                    sim.queues.get(location).map(VecDeque::len) == Some(queue.len())
+                })
+        };
+        let travelers_match = || {
+            sim.travelers
+                .iter()
+                .sorted_by_cached_key(|traveler| traveler_key(traveler))
+                .eq(self.travelers.iter())
+        };
+        let waiting_matches = || {
+            sim.waiting_for_queue
+                .iter()
+                .sorted_by_cached_key(|(traveler, _)| traveler_key(traveler))
+                .eq(self.waiting_for_queue.iter())
+        };
+        let queues_match = || {
+            self.queues
+                .iter()
+                .all(|(location, queue)| sim.queues.get(location) == Some(queue))
+        };
+
+        sim.output_stream.len() == self.output_stream_len
+            && sim.travelers.len() == self.travelers.len()
+            && sim.waiting_for_queue.len() == self.waiting_for_queue.len()
+            && sim.output == self.output
+            && queues_match_in_length()
+            && travelers_match()
+            && waiting_matches()
+            && queues_match()
+    }
+}
+
+impl Simulator {
+    /// Fails once the simulation comes back to a state it has already been in, since it will
+    /// keep repeating the ticks in between forever
+    pub(super) fn check_loop(&mut self) -> Result<(), SimulationError> {
+        if self.is_halted() {
+            return Ok(());
+        }
+        let Some(ref checkpoint) = self.loop_checkpoint else {
+            self.loop_checkpoint = Some(LoopCheckpoint::new(self, 1));
+            return Ok(());
+        };
+        let (start, length) = (checkpoint.tick, checkpoint.length);
+        if !checkpoint.matches(self) {
+            if self.ticks - start >= length {
+                self.loop_checkpoint = Some(LoopCheckpoint::new(self, length * 2));
+            }
+            return Ok(());
+        }
+
+        // replay the loop once to find the cells it passes through, stepping exactly as the
+        // simulation did, without jumping over any passes
+        let period = self.ticks - start;
+        let snapshot = self.snapshot();
+        let mut cells = vec![];
+        for _ in 0..period {
+            cells.extend(self.travelers.iter().map(|t| t.location));
+            if self
+                .collision_free()
+                .and_then(|()| self.check_moves())
+                .is_err()
+            {
+                break;
+            }
+            self.step_travelers();
+        }
+        self.restore(&snapshot);
+
+        Err(SimulationError::Loop {
+            period,
+            start,
+            cells: cells
+                .into_iter()
+                .unique()
+                .sorted_by_key(|c| (c.y, c.x))
+                .collect(),
+            travelers: self.travelers.to_vec(),
+        })
+    }
+}
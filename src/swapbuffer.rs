@@ -34,22 +34,18 @@ impl<T> SwapBuffer<T> {
         self.inactive.clear();
     }
 
-    pub fn try_swap<F, I, Err>(&mut self, fun: F) -> Result<(), Err>
+    /// Maps every element of the buffer into any number of new elements, which replace the old
+    /// contents of the buffer
+    pub fn swap<F, I>(&mut self, fun: F)
     where
-        F: FnMut(T) -> Result<I, Err>,
+        F: FnMut(T) -> I,
         I: IntoIterator<Item = T>,
     {
         self.inactive.reserve(self.active.len());
-        for elem in self.active.drain(..).map(fun) {
-            if let Ok(res) = elem {
-                self.inactive.extend(res)
-            } else if let Err(e) = elem {
-                return Err(e);
-            }
+        for res in self.active.drain(..).map(fun) {
+            self.inactive.extend(res)
         }
 
         std::mem::swap(&mut self.active, &mut self.inactive);
-
-        Ok(())
     }
 }
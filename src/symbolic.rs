@@ -0,0 +1,1077 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+};
+
+use glam::IVec2;
+use itertools::Itertools;
+
+use crate::{
+    l3x::{Direction, L3XCommand},
+    registers::{RegisterParseError, Registers},
+    simulator::{MatrixMode, Simulator},
+};
+
+/// How many paths are explored before the rest are given up on
+pub const MAX_PATHS: usize = 256;
+/// How many squares a single path may pass through before it is given up on
+pub const MAX_STEPS: usize = 100_000;
+
+/// An exponent, coefficient or number of ticks grew too large to be stored in an `i64`. Paths
+/// which run into this are given up on, rather than giving wrong results.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Overflow;
+
+/// An integer which depends linearly on the unknowns of the input, such as an exponent or a
+/// number of ticks
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Affine {
+    pub constant: i64,
+    /// The coefficient of each unknown. Unknowns with a coefficient of zero are left out.
+    pub terms: BTreeMap<String, i64>,
+}
+
+impl Affine {
+    pub fn constant(constant: i64) -> Self {
+        Self {
+            constant,
+            terms: BTreeMap::new(),
+        }
+    }
+
+    pub fn unknown(name: &str) -> Self {
+        Self {
+            constant: 0,
+            terms: [(name.to_string(), 1)].into(),
+        }
+    }
+
+    /// The value of this expression, if it does not depend on any unknowns
+    pub fn as_constant(&self) -> Option<i64> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    /// Replaces every occurrence of the unknown with the given expression
+    fn substitute(&self, name: &str, replacement: &Affine) -> Result<Self, Overflow> {
+        match self.terms.get(name) {
+            Some(&coefficient) => {
+                let mut rest = self.clone();
+                rest.terms.remove(name);
+                rest.checked_add(&replacement.checked_mul(coefficient)?)
+            }
+            None => Ok(self.clone()),
+        }
+    }
+
+    /// `floor(self / divisor)`, if it is still linear because every coefficient is a multiple of
+    /// the (positive) divisor
+    fn div_floor(&self, divisor: i64) -> Option<Self> {
+        self.terms
+            .values()
+            .all(|coefficient| coefficient % divisor == 0)
+            .then(|| Self {
+                constant: self.constant.div_euclid(divisor),
+                terms: self
+                    .terms
+                    .iter()
+                    .map(|(name, coefficient)| (name.clone(), coefficient / divisor))
+                    .collect(),
+            })
+    }
+
+    pub fn checked_add(mut self, rhs: &Affine) -> Result<Self, Overflow> {
+        self.constant = self.constant.checked_add(rhs.constant).ok_or(Overflow)?;
+        for (name, coefficient) in &rhs.terms {
+            let sum = self.terms.get(name).copied().unwrap_or(0);
+            let sum = sum.checked_add(*coefficient).ok_or(Overflow)?;
+            if sum == 0 {
+                self.terms.remove(name);
+            } else {
+                self.terms.insert(name.clone(), sum);
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn checked_add_constant(mut self, rhs: i64) -> Result<Self, Overflow> {
+        self.constant = self.constant.checked_add(rhs).ok_or(Overflow)?;
+        Ok(self)
+    }
+
+    pub fn checked_sub(self, rhs: &Affine) -> Result<Self, Overflow> {
+        self.checked_add(&rhs.checked_mul(-1)?)
+    }
+
+    pub fn checked_mul(&self, rhs: i64) -> Result<Self, Overflow> {
+        if rhs == 0 {
+            return Ok(Affine::default());
+        }
+        Ok(Affine {
+            constant: self.constant.checked_mul(rhs).ok_or(Overflow)?,
+            terms: self
+                .terms
+                .iter()
+                .map(|(name, coefficient)| {
+                    Ok((name.clone(), coefficient.checked_mul(rhs).ok_or(Overflow)?))
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl Display for Affine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for (name, &coefficient) in &self.terms {
+            let sign = match (first, coefficient < 0) {
+                (true, true) => "-",
+                (true, false) => "",
+                (false, true) => " - ",
+                (false, false) => " + ",
+            };
+            match coefficient.abs() {
+                1 => write!(f, "{sign}{name}")?,
+                c => write!(f, "{sign}{c}{name}")?,
+            }
+            first = false;
+        }
+        match (first, self.constant) {
+            (true, c) => write!(f, "{c}"),
+            (false, 0) => Ok(()),
+            (false, c) if c < 0 => write!(f, " - {}", -c),
+            (false, c) => write!(f, " + {c}"),
+        }
+    }
+}
+
+/// A number whose exponents may depend on the unknowns, such as `2^x · 3^(y + 1)`
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SymbolicValue(pub BTreeMap<u64, Affine>);
+
+impl SymbolicValue {
+    fn exponent(&self, prime: u64) -> Affine {
+        self.0.get(&prime).cloned().unwrap_or_default()
+    }
+
+    /// Multiplies by `prime^pow`, where `pow` may be negative
+    fn add_exponent(&mut self, prime: u64, pow: &Affine) -> Result<(), Overflow> {
+        let exponent = self.exponent(prime).checked_add(pow)?;
+        if exponent == Affine::default() {
+            self.0.remove(&prime);
+        } else {
+            self.0.insert(prime, exponent);
+        }
+        Ok(())
+    }
+
+    fn substitute(&mut self, name: &str, replacement: &Affine) -> Result<(), Overflow> {
+        for exponent in self.0.values_mut() {
+            *exponent = exponent.substitute(name, replacement)?;
+        }
+        self.0.retain(|_, exponent| *exponent != Affine::default());
+        Ok(())
+    }
+
+    /// The unknowns in the order they are first written
+    pub fn unknowns(&self) -> Vec<String> {
+        self.0
+            .values()
+            .flat_map(|exponent| exponent.terms.keys().cloned())
+            .unique()
+            .collect()
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SymbolicParseError {
+    #[error("Could not parse {0:?}, expected a factor such as 3, 2^5, or 2^x")]
+    BadFactor(String),
+    #[error(transparent)]
+    Registers(#[from] RegisterParseError),
+}
+
+fn is_unknown(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl FromStr for SymbolicValue {
+    type Err = SymbolicParseError;
+
+    /// Parses a product of factors such as `2^x · 3^y · 5^2`, where the factors may be separated
+    /// by `·` or `*`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut value = SymbolicValue::default();
+        for factor in s.split(['·', '*']).map(str::trim) {
+            let (base, pow) = match factor.split_once('^') {
+                Some((base, pow)) => (base.trim(), pow.trim()),
+                None => (factor, "1"),
+            };
+            let pow = match pow.parse::<i64>() {
+                Ok(pow) if pow >= 0 => Affine::constant(pow),
+                _ if is_unknown(pow) => Affine::unknown(pow),
+                _ => return Err(SymbolicParseError::BadFactor(factor.to_string())),
+            };
+            if base.is_empty() || !base.chars().all(|c| c.is_ascii_digit()) {
+                return Err(SymbolicParseError::BadFactor(factor.to_string()));
+            }
+            let Some(base) = powers(&base.parse()?) else {
+                return Err(SymbolicParseError::BadFactor(factor.to_string()));
+            };
+            let too_large = |_| SymbolicParseError::BadFactor(factor.to_string());
+            for (prime, times) in base {
+                value
+                    .add_exponent(prime, &pow.checked_mul(times).map_err(too_large)?)
+                    .map_err(too_large)?;
+            }
+        }
+        Ok(value)
+    }
+}
+
+fn superscript(f: &mut std::fmt::Formatter<'_>, exponent: &Affine) -> std::fmt::Result {
+    match (exponent.as_constant(), exponent.terms.len()) {
+        (Some(1), _) => Ok(()),
+        (Some(c), _) => write!(f, "^{c}"),
+        (None, 1) if exponent.constant == 0 && exponent.terms.values().all(|&c| c == 1) => {
+            write!(f, "^{exponent}")
+        }
+        _ => write!(f, "^({exponent})"),
+    }
+}
+
+impl Display for SymbolicValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "1");
+        }
+        for (ix, (prime, exponent)) in self.0.iter().enumerate() {
+            if ix > 0 {
+                write!(f, " · ")?;
+            }
+            write!(f, "{prime}")?;
+            superscript(f, exponent)?;
+        }
+        Ok(())
+    }
+}
+
+/// What is known about the unknowns on a path. Every unknown is a natural number.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Constraints {
+    /// Inclusive bounds on each unknown
+    bounds: BTreeMap<String, (i64, Option<i64>)>,
+    /// Inequalities `expression ≥ 0` between several unknowns
+    others: Vec<Affine>,
+}
+
+impl Constraints {
+    fn new(unknowns: &[String]) -> Self {
+        Self {
+            bounds: unknowns
+                .iter()
+                .map(|name| (name.clone(), (0, None)))
+                .collect(),
+            others: vec![],
+        }
+    }
+
+    /// The least and greatest values the expression can take within the bounds, where `None` is
+    /// unbounded. A bound too large to store is treated as unbounded, which only means less is
+    /// known about the expression.
+    fn range(&self, expression: &Affine) -> (Option<i64>, Option<i64>) {
+        expression.terms.iter().fold(
+            (Some(expression.constant), Some(expression.constant)),
+            |(min, max), (name, &coefficient)| {
+                let (lower, upper) = self.bounds.get(name).copied().unwrap_or((0, None));
+                let (lower, upper) = (
+                    lower.checked_mul(coefficient),
+                    upper.and_then(|u| u.checked_mul(coefficient)),
+                );
+                let (low, high) = if coefficient > 0 {
+                    (lower, upper)
+                } else {
+                    (upper, lower)
+                };
+                (
+                    min.zip(low).and_then(|(a, b)| a.checked_add(b)),
+                    max.zip(high).and_then(|(a, b)| a.checked_add(b)),
+                )
+            },
+        )
+    }
+
+    /// Adds the constraint `expression ≥ 0`. Returns `false` if the constraints can then no
+    /// longer be satisfied.
+    fn require(&mut self, expression: Affine) -> Result<bool, Overflow> {
+        match self.range(&expression) {
+            (_, Some(max)) if max < 0 => return Ok(false),
+            (Some(min), _) if min >= 0 => return Ok(true),
+            (_, Some(0)) => {
+                // only holds when every unknown is at whichever bound makes the expression largest
+                for (name, &coefficient) in &expression.terms {
+                    let bounds = self.bounds.entry(name.clone()).or_insert((0, None));
+                    *bounds = match *bounds {
+                        (_, Some(upper)) if coefficient > 0 => (upper, Some(upper)),
+                        (lower, _) => (lower, Some(lower)),
+                    };
+                }
+                return Ok(true);
+            }
+            _ => (),
+        }
+        if expression.terms.len() == 1 {
+            let (name, &coefficient) = expression.terms.iter().next().unwrap();
+            let bounds = self.bounds.entry(name.clone()).or_insert((0, None));
+            if coefficient > 0 {
+                // coefficient * x + c ≥ 0 means x ≥ ceil(-c / coefficient)
+                let lower = expression.constant.div_euclid(coefficient);
+                bounds.0 = bounds.0.max(lower.checked_neg().ok_or(Overflow)?);
+            } else {
+                let divisor = coefficient.checked_neg().ok_or(Overflow)?;
+                let upper = expression.constant.div_euclid(divisor);
+                bounds.1 = Some(bounds.1.map_or(upper, |u| u.min(upper)));
+            }
+            Ok(bounds.1.map(|upper| bounds.0 <= upper).unwrap_or(true))
+        } else {
+            self.others.push(expression);
+            Ok(true)
+        }
+    }
+
+    /// The unknowns whose bounds leave only one possible value
+    fn fixed(&self) -> Vec<(String, i64)> {
+        self.bounds
+            .iter()
+            .filter(|(_, &(lower, upper))| upper == Some(lower))
+            .map(|(name, &(lower, _))| (name.clone(), lower))
+            .collect()
+    }
+
+    /// Writes the unknown as `period * replacement + offset`, where `0 ≤ offset < period`.
+    /// Returns `false` if that leaves no possible values.
+    fn substitute(
+        &mut self,
+        name: &str,
+        replacement: &str,
+        period: i64,
+        offset: i64,
+    ) -> Result<bool, Overflow> {
+        let (lower, upper) = self.bounds.remove(name).unwrap_or((0, None));
+        // lower bounds are never negative, so only an upper bound which already rules out every
+        // value can go past the smallest `i64`, and it still does after saturating
+        let lower = (lower - offset).div_euclid(period)
+            + i64::from((lower - offset).rem_euclid(period) != 0);
+        let lower = lower.max(0);
+        let upper = upper.map(|u| u.saturating_sub(offset).div_euclid(period));
+        self.bounds.insert(replacement.to_string(), (lower, upper));
+        let value = Affine::unknown(replacement)
+            .checked_mul(period)?
+            .checked_add_constant(offset)?;
+        for other in &mut self.others {
+            *other = other.substitute(name, &value)?;
+        }
+        Ok(upper.map(|u| lower <= u).unwrap_or(true))
+    }
+}
+
+impl Display for Constraints {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bounds = self.bounds.iter().map(|(name, bounds)| match *bounds {
+            (lower, Some(upper)) if lower == upper => format!("{name} = {lower}"),
+            (lower, None) => format!("{name} ≥ {lower}"),
+            (0, Some(upper)) => format!("{name} ≤ {upper}"),
+            (lower, Some(upper)) => format!("{lower} ≤ {name} ≤ {upper}"),
+        });
+        let others = self.others.iter().map(|other| {
+            let terms = Affine {
+                constant: 0,
+                terms: other.terms.clone(),
+            };
+            format!("{terms} ≥ {}", -other.constant)
+        });
+        write!(f, "{}", bounds.chain(others).join(", "))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PathOutcome {
+    Output(SymbolicValue),
+    EmptyCell(IVec2),
+    OutOfBounds(IVec2),
+    /// The path reached a square which copies or queues numbers
+    Unsupported(IVec2),
+    /// The path repeats forever without changing which way it goes
+    Loops,
+    /// The path was still running after [MAX_STEPS] squares, or too many paths were explored
+    GaveUp,
+}
+
+impl Display for PathOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathOutcome::Output(value) => write!(f, "output = {value}"),
+            PathOutcome::EmptyCell(location) => {
+                write!(f, "moves onto the empty cell at {location}")
+            }
+            PathOutcome::OutOfBounds(location) => write!(f, "leaves the grid at {location}"),
+            PathOutcome::Unsupported(location) => {
+                write!(f, "reaches the unsupported square at {location}")
+            }
+            PathOutcome::Loops => write!(f, "loops forever"),
+            PathOutcome::GaveUp => write!(f, "gave up"),
+        }
+    }
+}
+
+/// Everything that happens to the inputs satisfying some path condition
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PathResult {
+    /// Unknowns which had to be split by their remainder, as `x = 2x' + 1`
+    pub substitutions: Vec<(String, Affine)>,
+    pub constraints: Constraints,
+    pub outcome: PathOutcome,
+    pub ticks: Affine,
+}
+
+impl Display for PathResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let condition = self
+            .substitutions
+            .iter()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .chain(Some(self.constraints.to_string()).filter(|c| !c.is_empty()))
+            .join(", ");
+        if condition.is_empty() {
+            write!(f, "always: {}", self.outcome)?;
+        } else if self.substitutions.is_empty()
+            && self.constraints.fixed().len() == self.constraints.bounds.len()
+            && self.constraints.others.is_empty()
+        {
+            write!(f, "for {condition}: {}", self.outcome)?;
+        } else {
+            write!(f, "for all {condition}: {}", self.outcome)?;
+        }
+        match self.outcome {
+            PathOutcome::Loops | PathOutcome::GaveUp => Ok(()),
+            _ => write!(f, " after {} ticks", self.ticks),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SymbolicError {
+    #[error("Symbolic execution only supports L3 programs")]
+    NotL3,
+}
+
+/// A divisibility check made on a path, which held as `expression ≥ 0`
+#[derive(Clone, Debug)]
+struct Check {
+    expression: Affine,
+    prime: u64,
+    /// Whether the expression grows (1) or shrinks (-1) with the exponent of the prime
+    sign: i64,
+}
+
+#[derive(Clone, Debug)]
+struct Visit {
+    step: usize,
+    checks: usize,
+    value: SymbolicValue,
+}
+
+/// One abstract traveler, standing for every input which satisfies its constraints
+#[derive(Clone, Debug)]
+struct Path {
+    location: IVec2,
+    direction: Direction,
+    value: SymbolicValue,
+    ticks: Affine,
+    constraints: Constraints,
+    substitutions: Vec<(String, Affine)>,
+    /// Squares passed through on this path
+    steps: usize,
+    checks: Vec<Check>,
+    /// The last time the path entered each square in each direction
+    visits: HashMap<(IVec2, Direction), Visit>,
+    /// Loops which started before this step did not simply multiply the value by a constant
+    barrier: usize,
+}
+
+enum Step {
+    Continue(Vec<Path>),
+    Finished(PathResult),
+}
+
+impl Path {
+    fn finish(mut self, mut outcome: PathOutcome) -> Result<PathResult, Overflow> {
+        // unknowns which only have one possible value are written as that value
+        for (name, value) in self.constraints.fixed() {
+            let value = Affine::constant(value);
+            self.ticks = self.ticks.substitute(&name, &value)?;
+            if let PathOutcome::Output(ref mut output) = outcome {
+                output.substitute(&name, &value)?;
+            }
+        }
+        Ok(PathResult {
+            substitutions: self.substitutions,
+            constraints: self.constraints,
+            outcome,
+            ticks: self.ticks,
+        })
+    }
+
+    /// Gives up on the inputs which satisfy the condition of the path
+    fn give_up(substitutions: Vec<(String, Affine)>, constraints: Constraints) -> PathResult {
+        PathResult {
+            substitutions,
+            constraints,
+            outcome: PathOutcome::GaveUp,
+            ticks: Affine::default(),
+        }
+    }
+
+    fn direct(mut self, direction: Direction) -> Result<Self, Overflow> {
+        self.location += IVec2::from(direction);
+        self.direction = direction;
+        self.steps += 1;
+        self.ticks = self.ticks.checked_add_constant(1)?;
+        Ok(self)
+    }
+
+    /// Adds a check to the path, returning `None` if it cannot hold
+    fn check(
+        mut self,
+        expression: Affine,
+        prime: u64,
+        sign: i64,
+    ) -> Result<Option<Self>, Overflow> {
+        if !self.constraints.require(expression.clone())? {
+            return Ok(None);
+        }
+        self.checks.push(Check {
+            expression,
+            prime,
+            sign,
+        });
+        Ok(Some(self))
+    }
+
+    fn substitute(&mut self, name: &str, period: i64, offset: i64) -> Result<bool, Overflow> {
+        let replacement = format!("{name}'");
+        let value = Affine::unknown(&replacement)
+            .checked_mul(period)?
+            .checked_add_constant(offset)?;
+        for (_, substitution) in &mut self.substitutions {
+            *substitution = substitution.substitute(name, &value)?;
+        }
+        self.substitutions.push((name.to_string(), value.clone()));
+        self.value.substitute(name, &value)?;
+        self.ticks = self.ticks.substitute(name, &value)?;
+        for check in &mut self.checks {
+            check.expression = check.expression.substitute(name, &value)?;
+        }
+        for visit in self.visits.values_mut() {
+            visit.value.substitute(name, &value)?;
+        }
+        self.constraints
+            .substitute(name, &replacement, period, offset)
+    }
+
+    /// Skips ahead to the last pass through a loop, given that a pass has just been made since
+    /// `visit`. The loop keeps going for as long as every check made in the pass still holds;
+    /// since each pass multiplies the value by the same constant, the checks change by a
+    /// constant each pass, so the number of passes can be worked out directly. Returns `None` if
+    /// the loop cannot be skipped this way.
+    fn accelerate(self, visit: &Visit) -> Result<Option<Step>, Overflow> {
+        let mut delta = BTreeMap::new();
+        for &prime in visit.value.0.keys().chain(self.value.0.keys()).unique() {
+            let change = self
+                .value
+                .exponent(prime)
+                .checked_sub(&visit.value.exponent(prime))?;
+            let Some(change) = change.as_constant() else {
+                return Ok(None);
+            };
+            delta.insert(prime, change);
+        }
+        // each check `e ≥ 0` becomes `e - pass * shrink ≥ 0` on later passes, so the ones which
+        // shrink eventually fail
+        let shrink = |check: &Check| {
+            let change = delta.get(&check.prime).copied().unwrap_or(0);
+            check
+                .sign
+                .checked_mul(change)
+                .and_then(i64::checked_neg)
+                .ok_or(Overflow)
+        };
+        let limits: Vec<(Affine, i64)> = self.checks[visit.checks..]
+            .iter()
+            .map(|check| Ok((check.expression.clone(), shrink(check)?)))
+            .filter_ok(|&(_, shrink)| shrink > 0)
+            .try_collect()?;
+        if limits.is_empty() {
+            return Ok(Some(Step::Finished(self.finish(PathOutcome::Loops)?)));
+        }
+
+        let paths = match limits.as_slice() {
+            [(expression, shrink)] if expression.div_floor(*shrink).is_none() => {
+                // split the unknown by its remainder so that the number of passes is linear
+                let Ok((name, &coefficient)) = expression.terms.iter().exactly_one() else {
+                    return Ok(None);
+                };
+                let period = shrink / gcd(coefficient.checked_abs().ok_or(Overflow)?, *shrink);
+                if period > MAX_PATHS as i64 {
+                    return Ok(None);
+                }
+                let mut paths = vec![];
+                for offset in 0..period {
+                    let mut path = self.clone();
+                    if path.substitute(name, period, offset)? {
+                        paths.push(path);
+                    }
+                }
+                paths
+            }
+            _ => vec![self],
+        };
+
+        let mut jumped = vec![];
+        for path in paths {
+            let mut limits = vec![];
+            for check in &path.checks[visit.checks..] {
+                let shrink = shrink(check)?;
+                if shrink > 0 {
+                    let Some(limit) = check.expression.div_floor(shrink) else {
+                        return Ok(None);
+                    };
+                    limits.push(limit);
+                }
+            }
+            // the loop runs out at whichever check fails first, so each is tried as the first
+            'first: for (ix, passes) in limits.iter().enumerate() {
+                let mut path = path.clone();
+                for (other_ix, other) in limits.iter().enumerate() {
+                    let difference = other.clone().checked_sub(passes)?;
+                    let difference = if other_ix < ix {
+                        difference.checked_add_constant(-1)?
+                    } else {
+                        difference
+                    };
+                    if other_ix != ix && !path.constraints.require(difference)? {
+                        continue 'first;
+                    }
+                }
+                for (&prime, &d) in &delta {
+                    path.value.add_exponent(prime, &passes.checked_mul(d)?)?;
+                }
+                let pass_ticks = passes.checked_mul((path.steps - visit.step) as i64)?;
+                path.ticks = path.ticks.checked_add(&pass_ticks)?;
+                path.barrier = path.steps;
+                path.visits.clear();
+                jumped.push(path);
+            }
+        }
+        Ok(Some(Step::Continue(jumped)))
+    }
+
+    fn step(mut self, program: &Simulator) -> Result<Step, Overflow> {
+        let dims = program.dims.as_ivec2();
+        let location = self.location;
+        if location == dims - IVec2::new(1, 0) {
+            self.ticks = self.ticks.checked_add_constant(1)?;
+            let value = self.value.clone();
+            return Ok(Step::Finished(self.finish(PathOutcome::Output(value))?));
+        }
+        if !(location.cmpge(IVec2::ZERO).all() && location.cmplt(dims).all()) {
+            return Ok(Step::Finished(
+                self.finish(PathOutcome::OutOfBounds(location))?,
+            ));
+        }
+        let Some(instruction) = program.instructions.get(&location) else {
+            return Ok(Step::Finished(
+                self.finish(PathOutcome::EmptyCell(location))?,
+            ));
+        };
+
+        let key = (location, self.direction);
+        if let Some(visit) = self.visits.get(&key).filter(|v| v.step >= self.barrier) {
+            if let Some(step) = self.clone().accelerate(&visit.clone())? {
+                return Ok(step);
+            }
+        }
+        self.visits.insert(
+            key,
+            Visit {
+                step: self.steps,
+                checks: self.checks.len(),
+                value: self.value.clone(),
+            },
+        );
+
+        let direction = instruction.direction;
+        let successors = match instruction.command {
+            L3XCommand::Multiply(ref with) if self.direction == direction => {
+                let Some(with) = powers(with) else {
+                    return Ok(Step::Finished(
+                        self.finish(PathOutcome::Unsupported(location))?,
+                    ));
+                };
+                for (prime, pow) in with {
+                    self.value.add_exponent(prime, &Affine::constant(pow))?;
+                }
+                vec![self.direct(direction)?]
+            }
+            L3XCommand::Multiply(ref with) => {
+                let Some(with) = powers(with) else {
+                    return Ok(Step::Finished(
+                        self.finish(PathOutcome::Unsupported(location))?,
+                    ));
+                };
+                // the number is divisible if every prime divides it enough, so there is one path
+                // for each prime which could be the first not to
+                let mut successors = vec![];
+                let mut divisible = Some(self);
+                for &(prime, pow) in &with {
+                    let Some(path) = divisible else { break };
+                    let exponent = path.value.exponent(prime);
+                    let not_divisible = Affine::constant(pow - 1).checked_sub(&exponent)?;
+                    if let Some(path) = path.clone().check(not_divisible, prime, -1)? {
+                        successors.push(path.direct(direction.opposite())?);
+                    }
+                    divisible = path.check(exponent.checked_add_constant(-pow)?, prime, 1)?;
+                }
+                if let Some(mut path) = divisible {
+                    for &(prime, pow) in &with {
+                        path.value.add_exponent(prime, &Affine::constant(-pow))?;
+                    }
+                    successors.push(path.direct(direction)?);
+                }
+                successors
+            }
+            L3XCommand::Annihilate => {
+                self.value = SymbolicValue::default();
+                self.barrier = self.steps + 1;
+                vec![self.direct(direction)?]
+            }
+            L3XCommand::Duplicate | L3XCommand::Queue => {
+                return Ok(Step::Finished(
+                    self.finish(PathOutcome::Unsupported(location))?,
+                ));
+            }
+        };
+        Ok(Step::Continue(successors))
+    }
+}
+
+/// The power of each prime in a constant, unless one is too large to work with
+fn powers(value: &Registers) -> Option<Vec<(u64, i64)>> {
+    value
+        .0
+        .iter()
+        .map(|(prime, pow)| Some((*prime, i64::try_from(pow.to_u64()?).ok()?)))
+        .collect()
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Runs the program on every input of the given form at once, splitting the inputs up whenever
+/// they would take different paths through the grid. Loops which multiply the number by a
+/// constant on every pass are skipped over, so that the results hold for inputs of any size.
+/// The limits of the ruleset are not checked.
+///
+/// The exploration is worked on one square at a time, so that it can be stopped at any point.
+struct Exploration {
+    program: Simulator,
+    pending: Vec<Path>,
+    results: Vec<PathResult>,
+}
+
+impl Exploration {
+    fn new(program: &Simulator, input: &SymbolicValue) -> Result<Self, SymbolicError> {
+        if program.mode != MatrixMode::L3 {
+            return Err(SymbolicError::NotL3);
+        }
+        Ok(Self {
+            program: program.program_copy(),
+            pending: vec![Path {
+                location: IVec2::ZERO,
+                direction: Direction::Down,
+                value: input.clone(),
+                ticks: Affine::default(),
+                constraints: Constraints::new(&input.unknowns()),
+                substitutions: vec![],
+                steps: 0,
+                checks: vec![],
+                visits: HashMap::new(),
+                barrier: 0,
+            }],
+            results: vec![],
+        })
+    }
+
+    /// Works on the exploration for as long as `keep_going` allows, always taking at least one
+    /// step. Returns the results once every path has finished.
+    fn run(&mut self, keep_going: impl Fn() -> bool) -> Option<Vec<PathResult>> {
+        while let Some(path) = self.pending.pop() {
+            if path.steps > MAX_STEPS || self.results.len() + self.pending.len() >= MAX_PATHS {
+                self.results
+                    .push(Path::give_up(path.substitutions, path.constraints));
+            } else {
+                // kept so that the inputs on the path can still be reported if it overflows
+                let condition = (path.substitutions.clone(), path.constraints.clone());
+                match path.step(&self.program) {
+                    Ok(Step::Continue(paths)) => self.pending.extend(paths),
+                    Ok(Step::Finished(result)) => self.results.push(result),
+                    Err(Overflow) => self.results.push(Path::give_up(condition.0, condition.1)),
+                }
+            }
+            if !keep_going() {
+                break;
+            }
+        }
+        self.pending
+            .is_empty()
+            .then(|| std::mem::take(&mut self.results))
+    }
+}
+
+/// Explores a program as described on [Exploration], without holding up the frame loop. Native
+/// builds explore on another thread as soon as this is created; web builds have no threads, so the work is done whenever this is
+/// polled. Dropping it cancels the exploration.
+pub struct Exploring {
+    results: Option<Vec<PathResult>>,
+    #[cfg(target_arch = "wasm32")]
+    work: Exploration,
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: std::sync::mpsc::Receiver<Vec<PathResult>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    finished_paths: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Exploring {
+    pub fn start(program: &Simulator, input: &SymbolicValue) -> Result<Self, SymbolicError> {
+        let work = Exploration::new(program, input)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use std::sync::{
+                atomic::{AtomicBool, AtomicUsize, Ordering},
+                Arc,
+            };
+
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let finished_paths = Arc::new(AtomicUsize::new(0));
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let (finished, cancelled_flag) = (finished_paths.clone(), cancelled.clone());
+            let mut work = work;
+            std::thread::spawn(move || {
+                while !cancelled_flag.load(Ordering::Relaxed) {
+                    if let Some(results) = work.run(|| false) {
+                        // the receiver is gone if the exploration was cancelled
+                        sender.send(results).ok();
+                        break;
+                    }
+                    finished.store(work.results.len(), Ordering::Relaxed);
+                }
+            });
+            Ok(Self {
+                results: None,
+                receiver,
+                finished_paths,
+                cancelled,
+            })
+        }
+        #[cfg(target_arch = "wasm32")]
+        Ok(Self {
+            results: None,
+            work,
+        })
+    }
+
+    /// Checks on the exploration. On web builds, works on it for as long as `keep_going` allows.
+    /// Returns the results once they are all in.
+    pub fn poll(
+        &mut self,
+        #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))] keep_going: impl Fn() -> bool,
+    ) -> Option<&[PathResult]> {
+        if self.results.is_none() {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                self.results = self.receiver.try_recv().ok();
+            }
+            #[cfg(target_arch = "wasm32")]
+            {
+                self.results = self.work.run(keep_going);
+            }
+        }
+        self.results.as_deref()
+    }
+
+    /// How many paths have been followed to their end so far
+    pub fn finished_paths(&self) -> usize {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self
+            .finished_paths
+            .load(std::sync::atomic::Ordering::Relaxed);
+        #[cfg(target_arch = "wasm32")]
+        return self.work.results.len();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Exploring {
+    fn drop(&mut self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simulator::test_programs, testcase::MAX_TICKS};
+
+    fn grid(rows: &[&[&str]]) -> Simulator {
+        test_programs::program(MatrixMode::L3, rows)
+    }
+
+    fn explore(
+        program: &Simulator,
+        input: &SymbolicValue,
+    ) -> Result<Vec<PathResult>, SymbolicError> {
+        let mut exploring = Exploring::start(program, input)?;
+        loop {
+            if let Some(results) = exploring.poll(|| true) {
+                return Ok(results.to_vec());
+            }
+        }
+    }
+
+    fn explore_strings(program: &Simulator, input: &str) -> Vec<String> {
+        explore(program, &input.parse().unwrap())
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .sorted()
+            .collect()
+    }
+
+    /// Checks the number of ticks against a concrete run of the program
+    fn assert_ticks(program: &Simulator, input: u64, ticks: usize) {
+        let mut sim = program.program_copy();
+        sim.start(Registers::try_from(input).unwrap(), vec![])
+            .unwrap();
+        assert_eq!(sim.run(MAX_TICKS), Ok(true));
+        assert_eq!(sim.ticks(), ticks);
+    }
+
+    #[test]
+    fn parse_values() {
+        let value: SymbolicValue = "2^x · 3^y*5^2".parse().unwrap();
+        assert_eq!(value.to_string(), "2^x · 3^y · 5^2");
+        assert_eq!(value.unknowns(), vec!["x", "y"]);
+        assert_eq!(
+            "6^n * 2".parse::<SymbolicValue>().unwrap().to_string(),
+            "2^(n + 1) · 3^n"
+        );
+        assert_eq!("1".parse::<SymbolicValue>().unwrap().to_string(), "1");
+        assert!(matches!(
+            "2^x^2".parse::<SymbolicValue>(),
+            Err(SymbolicParseError::BadFactor(_))
+        ));
+        assert!("x^2".parse::<SymbolicValue>().is_err());
+    }
+
+    #[test]
+    fn transfer() {
+        let program = test_programs::transfer();
+        assert_eq!(
+            explore_strings(&program, "2^x"),
+            vec![
+                "for all x ≥ 1: output = 3^x after 6x + 5 ticks",
+                "for x = 0: output = 1 after 5 ticks",
+            ]
+        );
+        assert_ticks(&program, 1 << 7, 6 * 7 + 5);
+        assert_eq!(
+            explore_strings(&program, "2^x · 2^y"),
+            vec![
+                "for all x ≥ 0, y ≥ 0, x + y ≥ 1: output = 3^(x + y) after 6x + 6y + 5 ticks",
+                "for x = 0, y = 0: output = 1 after 5 ticks",
+            ]
+        );
+        assert_eq!(
+            explore_strings(&program, "2^x · 5"),
+            vec![
+                "for all x ≥ 1: output = 3^x · 5 after 6x + 5 ticks",
+                "for x = 0: output = 5 after 5 ticks",
+            ]
+        );
+    }
+
+    #[test]
+    fn halve() {
+        let program = grid(&[&["1D", "1L"], &["1D", "3U"], &["1R", "4U"]]);
+        assert_eq!(
+            explore_strings(&program, "2^x"),
+            vec![
+                "for all x = 2x' + 1, x' ≥ 1: output = 2 · 3^x' after 6x' + 5 ticks",
+                "for all x = 2x', x' ≥ 1: output = 3^x' after 6x' + 5 ticks",
+                "for all x ≤ 1: output = 2^x after 5 ticks",
+            ]
+        );
+        assert_ticks(&program, 1 << 9, 6 * 4 + 5);
+    }
+
+    #[test]
+    fn overflow() {
+        let program = test_programs::transfer();
+        assert_eq!(
+            explore_strings(&program, "2^x · 3^9223372036854775807"),
+            vec![
+                "for all x ≥ 1: gave up",
+                "for x = 0: output = 3^9223372036854775807 after 5 ticks",
+            ]
+        );
+        assert!(matches!(
+            "4^4611686018427387904".parse::<SymbolicValue>(),
+            Err(SymbolicParseError::BadFactor(_))
+        ));
+    }
+
+    #[test]
+    fn loops_and_errors() {
+        let program = grid(&[&["1R", "1D"], &["1U", "1L"]]);
+        assert_eq!(
+            explore_strings(&program, "2^x"),
+            vec!["for all x ≥ 0: loops forever"]
+        );
+
+        let program = grid(&[&["2R", "1D"], &["1U", "%L"]]);
+        assert_eq!(
+            explore_strings(&program, "2^x"),
+            vec![
+                "for all x ≥ 1: reaches the unsupported square at [1, 1] after 2 ticks",
+                "for x = 0: leaves the grid at [-1, 0] after 1 ticks",
+            ]
+        );
+
+        let mut l3x = program.program_copy();
+        l3x.mode = MatrixMode::L3X;
+        assert_eq!(
+            explore(&l3x, &SymbolicValue::default()),
+            Err(SymbolicError::NotL3)
+        );
+    }
+}
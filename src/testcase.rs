@@ -0,0 +1,188 @@
+use itertools::Itertools;
+
+use crate::{
+    batch::{BatchResult, BatchRun, Outcome},
+    factorize::Unfactored,
+    registers::{split_list, RegisterParseError, Registers},
+    simulator::{MatrixMode, SimulationError, Simulator},
+};
+
+/// How long a test case may run before it is considered not to halt
+pub const MAX_TICKS: usize = 1_000_000;
+
+/// Parses a comma-separated list of values, as used for input and output streams
+pub fn parse_stream(text: &str) -> Result<Vec<Unfactored>, RegisterParseError> {
+    split_list(text)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .try_collect()
+}
+
+/// Parses the fields of a test case into the values to factor, in the order taken by
+/// [TestCase::from_values], together with the length of the input stream
+pub fn parse_case(
+    input: &str,
+    stream: &str,
+    expected_output: &str,
+    expected_stream: &str,
+) -> Result<(Vec<Unfactored>, usize), RegisterParseError> {
+    let stream = parse_stream(stream)?;
+    let stream_len = stream.len();
+    let values = std::iter::once(input.parse()?)
+        .chain(stream)
+        .chain(std::iter::once(expected_output.parse()?))
+        .chain(parse_stream(expected_stream)?)
+        .collect();
+    Ok((values, stream_len))
+}
+
+/// One run of a program together with what it is expected to produce. The streams are only used
+/// in L3X mode.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TestCase {
+    pub input: Registers,
+    pub stream: Vec<Registers>,
+    pub expected_output: Registers,
+    pub expected_stream: Vec<Registers>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Verdict {
+    Passed,
+    /// The program halted, but with the wrong output
+    Failed,
+    Errored(SimulationError),
+    /// The program was still running after [MAX_TICKS] ticks
+    TimedOut,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TestResult {
+    pub verdict: Verdict,
+    pub output: Option<Registers>,
+    pub output_stream: Vec<Registers>,
+    pub steps: usize,
+}
+
+/// Starts running each case on a fresh copy of the given program, in the background. The results
+/// come out in the same order as the cases, and are judged with [TestCase::judge].
+pub fn start_run(program: &Simulator, cases: &[TestCase], max_ticks: usize) -> BatchRun {
+    let inputs = cases
+        .iter()
+        .map(|case| (case.input.clone(), case.stream.clone()))
+        .collect();
+    BatchRun::start(program, inputs, max_ticks)
+}
+
+impl TestCase {
+    /// Puts a case back together from its factored values, given in the order input, input
+    /// stream, expected output, expected stream
+    pub fn from_values(values: Vec<Registers>, stream_len: usize) -> Self {
+        let mut values = values.into_iter();
+        TestCase {
+            input: values.next().unwrap_or_default(),
+            stream: values.by_ref().take(stream_len).collect(),
+            expected_output: values.next().unwrap_or_default(),
+            expected_stream: values.collect(),
+        }
+    }
+
+    /// Compares what a program in the given mode did with this case's input against what was
+    /// expected of it
+    pub fn judge(&self, result: &BatchResult, mode: MatrixMode) -> TestResult {
+        let verdict = match result.outcome {
+            Outcome::Halted if self.matches(result, mode) => Verdict::Passed,
+            Outcome::Halted => Verdict::Failed,
+            Outcome::TimedOut => Verdict::TimedOut,
+            Outcome::Errored(ref e) => Verdict::Errored(e.clone()),
+        };
+        TestResult {
+            verdict,
+            output: result.output.clone(),
+            output_stream: result.output_stream.clone(),
+            steps: result.steps,
+        }
+    }
+
+    fn matches(&self, result: &BatchResult, mode: MatrixMode) -> bool {
+        result.output.as_ref() == Some(&self.expected_output)
+            && (mode == MatrixMode::L3 || result.output_stream == self.expected_stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{factorize::Factoring, simulator::test_programs::transfer};
+
+    fn case(input: &str, expected: &str) -> TestCase {
+        TestCase {
+            input: input.parse().unwrap(),
+            stream: vec![],
+            expected_output: expected.parse().unwrap(),
+            expected_stream: vec![],
+        }
+    }
+
+    fn run(program: &Simulator, cases: &[TestCase], max_ticks: usize) -> Vec<TestResult> {
+        let mut run = start_run(program, cases, max_ticks);
+        while !run.is_finished() {
+            run.poll(|| true);
+        }
+        cases
+            .iter()
+            .zip(run.results())
+            .map(|(case, result)| case.judge(result, program.mode))
+            .collect()
+    }
+
+    #[test]
+    fn pass_and_fail() {
+        let program = transfer();
+        let cases = [case("16", "81"), case("16", "27")];
+        let [passed, failed] = &run(&program, &cases, MAX_TICKS)[..] else {
+            panic!("expected two results");
+        };
+        assert_eq!(passed.verdict, Verdict::Passed);
+        assert_eq!(passed.output, Some(Registers::from(vec![(3, 4)])));
+        assert_eq!(failed.verdict, Verdict::Failed);
+        assert_eq!(failed.steps, passed.steps);
+
+        assert_eq!(run(&program, &cases[..1], 3)[0].verdict, Verdict::TimedOut);
+    }
+
+    #[test]
+    fn streams() {
+        let factored = |text| Factoring::start(parse_stream(text).unwrap()).wait();
+        assert_eq!(
+            factored(" 2, 4,,8 "),
+            Ok(vec![
+                Registers::from(vec![(2, 1)]),
+                Registers::from(vec![(2, 2)]),
+                Registers::from(vec![(2, 3)])
+            ])
+        );
+        assert_eq!(factored(""), Ok(vec![]));
+        assert_eq!(
+            factored("{2:1,3:1}, 5"),
+            Ok(vec![
+                Registers::from(vec![(2, 1), (3, 1)]),
+                Registers::from(vec![(5, 1)])
+            ])
+        );
+        assert!(parse_stream("2,0").is_err());
+
+        let (values, stream_len) = parse_case("12", "2, 3^2", "1", "").unwrap();
+        let case = TestCase::from_values(Factoring::start(values).wait().unwrap(), stream_len);
+        assert_eq!(
+            case,
+            TestCase {
+                input: Registers::from(vec![(2, 2), (3, 1)]),
+                stream: vec![Registers::from(vec![(2, 1)]), Registers::from(vec![(3, 2)])],
+                expected_output: Registers::ONE,
+                expected_stream: vec![],
+            }
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+
+use crate::simulator::{Simulator, Snapshot};
+
+/// The number of snapshots kept in the timeline. Beyond this, the oldest ticks are forgotten.
+const MAX_SNAPSHOTS: usize = 5000;
+
+/// The number of ticks between snapshots. The ticks in between are returned to by stepping
+/// forward from the snapshot before them.
+const SNAPSHOT_INTERVAL: usize = 32;
+
+/// A history of snapshots of a simulation, taken every few ticks, which can be scrubbed through.
+/// Stepping back does not forget the ticks after the simulation's current one until the
+/// simulation moves forward from there.
+#[derive(Default)]
+pub struct Timeline {
+    snapshots: VecDeque<Snapshot>,
+    /// The latest tick which has been simulated, which may be after the last snapshot
+    latest: usize,
+}
+
+impl Timeline {
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.latest = 0;
+    }
+
+    /// Records the state of the simulation after a tick, if enough ticks have passed since the
+    /// last snapshot. Anything recorded after this tick is discarded, since the simulation has
+    /// moved on from there instead.
+    pub fn record(&mut self, sim: &Simulator) {
+        self.forget_after(sim.ticks());
+        let due = self
+            .snapshots
+            .back()
+            .is_none_or(|last| sim.ticks() >= last.ticks() + SNAPSHOT_INTERVAL);
+        if due {
+            self.push(sim.snapshot());
+        }
+    }
+
+    /// Records the state of the simulation, however recently the last snapshot was taken
+    pub fn record_now(&mut self, sim: &Simulator) {
+        self.forget_after(sim.ticks());
+        if self.snapshots.back().map(Snapshot::ticks) != Some(sim.ticks()) {
+            self.push(sim.snapshot());
+        }
+    }
+
+    fn forget_after(&mut self, tick: usize) {
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.ticks() > tick)
+        {
+            self.snapshots.pop_back();
+        }
+        self.latest = tick;
+    }
+
+    fn push(&mut self, snapshot: Snapshot) {
+        self.snapshots.push_back(snapshot);
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// The earliest and latest ticks which can be returned to
+    pub fn range(&self) -> Option<(usize, usize)> {
+        Some((self.snapshots.front()?.ticks(), self.latest))
+    }
+
+    pub fn can_step_back(&self, tick: usize) -> bool {
+        self.snapshots
+            .front()
+            .is_some_and(|first| first.ticks() < tick)
+    }
+
+    /// The latest snapshot taken at or before the given tick
+    pub fn seek(&self, tick: usize) -> Option<&Snapshot> {
+        let ix = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.ticks() <= tick);
+        self.snapshots.get(ix.checked_sub(1)?)
+    }
+}
@@ -0,0 +1,208 @@
+use glam::IVec2;
+use serde::Serialize;
+
+use crate::{l3x::Direction, registers::Registers, traveler::Traveler};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceFormat {
+    JsonLines,
+    Csv,
+}
+
+impl TraceFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TraceFormat::JsonLines => "jsonl",
+            TraceFormat::Csv => "csv",
+        }
+    }
+
+    /// Guesses the format from a file extension, preferring JSON Lines
+    pub fn from_extension(ext: &str) -> Self {
+        if ext.eq_ignore_ascii_case("csv") {
+            TraceFormat::Csv
+        } else {
+            TraceFormat::JsonLines
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TraceExportError {
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TraceEvent {
+    /// Where a traveler is at the end of a tick
+    Traveler(Traveler),
+    QueuePush {
+        location: IVec2,
+        value: Registers,
+    },
+    QueuePop {
+        location: IVec2,
+        value: Registers,
+    },
+    Output(Registers),
+    StreamOutput(Registers),
+}
+
+/// One line of an exported trace. Every kind of event shares the same columns so that JSON Lines
+/// and CSV exports can be loaded the same way; the columns an event does not use are left empty.
+#[derive(Serialize)]
+struct TraceRow {
+    tick: usize,
+    event: &'static str,
+    x: Option<i32>,
+    y: Option<i32>,
+    direction: Option<&'static str>,
+    value: String,
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+/// Everything that happened during a simulation, in order. Tick 0 holds the starting traveler,
+/// and the events of each later tick are recorded as the simulation steps into it.
+#[derive(Default, Clone, Debug)]
+pub struct Trace {
+    events: Vec<(usize, TraceEvent)>,
+}
+
+impl Trace {
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn events(&self) -> &[(usize, TraceEvent)] {
+        &self.events
+    }
+
+    pub fn record(&mut self, tick: usize, event: TraceEvent) {
+        self.events.push((tick, event));
+    }
+
+    /// Forgets every event after the given tick, for when the simulation is rewound
+    pub fn truncate(&mut self, tick: usize) {
+        let end = self.events.partition_point(|&(t, _)| t <= tick);
+        self.events.truncate(end);
+    }
+
+    fn rows(&self) -> impl Iterator<Item = TraceRow> + '_ {
+        self.events.iter().map(|(tick, event)| {
+            let (event, location, direction, value) = match event {
+                TraceEvent::Traveler(t) => {
+                    ("traveler", Some(t.location), Some(t.direction), &t.value)
+                }
+                TraceEvent::QueuePush { location, value } => {
+                    ("queue_push", Some(*location), None, value)
+                }
+                TraceEvent::QueuePop { location, value } => {
+                    ("queue_pop", Some(*location), None, value)
+                }
+                TraceEvent::Output(value) => ("output", None, None, value),
+                TraceEvent::StreamOutput(value) => ("stream_output", None, None, value),
+            };
+            TraceRow {
+                tick: *tick,
+                event,
+                x: location.map(|l| l.x),
+                y: location.map(|l| l.y),
+                direction: direction.map(direction_name),
+                value: value.to_string(),
+            }
+        })
+    }
+
+    pub fn export(&self, format: TraceFormat) -> Result<Vec<u8>, TraceExportError> {
+        let mut buf_out = Vec::new();
+        match format {
+            TraceFormat::JsonLines => {
+                for row in self.rows() {
+                    serde_json::to_writer(&mut buf_out, &row)?;
+                    buf_out.push(b'\n');
+                }
+            }
+            TraceFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(&mut buf_out);
+                for row in self.rows() {
+                    writer.serialize(row)?;
+                }
+                writer.flush().map_err(csv::Error::from)?;
+            }
+        }
+        Ok(buf_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::ivec2;
+
+    use super::*;
+
+    fn trace() -> Trace {
+        let mut trace = Trace::default();
+        trace.record(
+            0,
+            TraceEvent::Traveler(Traveler {
+                value: Registers::from(vec![(2, 1)]),
+                location: ivec2(0, 0),
+                direction: Direction::Down,
+            }),
+        );
+        trace.record(
+            1,
+            TraceEvent::QueuePush {
+                location: ivec2(0, 1),
+                value: Registers::from(vec![(2, 1)]),
+            },
+        );
+        trace.record(2, TraceEvent::Output(Registers::ONE));
+        trace
+    }
+
+    #[test]
+    fn export_formats() {
+        let jsonl = String::from_utf8(trace().export(TraceFormat::JsonLines).unwrap()).unwrap();
+        assert_eq!(
+            jsonl.lines().next(),
+            Some(r#"{"tick":0,"event":"traveler","x":0,"y":0,"direction":"down","value":"2"}"#)
+        );
+        assert_eq!(jsonl.lines().count(), 3);
+
+        let csv = String::from_utf8(trace().export(TraceFormat::Csv).unwrap()).unwrap();
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "tick,event,x,y,direction,value",
+                "0,traveler,0,0,down,2",
+                "1,queue_push,0,1,,2",
+                "2,output,,,,1",
+            ]
+        );
+    }
+
+    #[test]
+    fn truncate() {
+        let mut trace = trace();
+        trace.truncate(1);
+        assert_eq!(trace.events().len(), 2);
+        trace.truncate(0);
+        assert_eq!(trace.events().len(), 1);
+    }
+}
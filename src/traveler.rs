@@ -3,7 +3,7 @@ use std::fmt::Display;
 
 use crate::{
     l3x::Direction,
-    registers::Registers,
+    registers::{DisplayMode, Registers},
     sound::chord::{TwelveToneNote, TwelveTonePitch},
 };
 
@@ -21,7 +21,7 @@ const PITCHES: &[(u64, TwelveTonePitch)] = {
     ]
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Traveler {
     pub value: Registers,
     pub location: IVec2,
@@ -53,17 +53,23 @@ impl Traveler {
     pub fn pitches(&self) -> Vec<TwelveToneNote> {
         let mut v = Vec::new();
         let mut ix = 0;
-        'outer: for &(test_prime, magnitude) in &self.value.0 {
+        'outer: for (test_prime, magnitude) in &self.value.0 {
+            let magnitude = magnitude.to_u64().map(|m| m as f32).unwrap_or(f32::MAX);
             while let Some(&(prime, pitch)) = PITCHES.get(ix) {
                 ix += 1;
-                if prime == test_prime {
-                    v.push(pitch.vol(magnitude as f32));
+                if prime == *test_prime {
+                    v.push(pitch.vol(magnitude));
                     continue 'outer;
                 }
             }
         }
         v
     }
+
+    /// Describes the traveler with its value shown in the given mode
+    pub fn describe(&self, mode: DisplayMode) -> String {
+        format!("{}: {:?}", self.value.display(mode), self.direction)
+    }
 }
 
 impl Display for Traveler {
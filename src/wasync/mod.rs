@@ -1,3 +1,5 @@
+use crate::{matrix::MatrixMode, trace::TraceFormat};
+
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "wasm32")] {
         mod native {
@@ -11,3 +13,54 @@ cfg_if::cfg_if! {
         pub use native::pc::*;
     }
 }
+
+/// The extension of project files, which hold a program along with its inputs, tests and notes
+pub const PROJECT_EXTENSION: &str = "l3xproj";
+
+/// The kinds of file which can be handed to the user
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportKind {
+    Program(MatrixMode),
+    Project,
+    Trace(TraceFormat),
+    BatchResults,
+}
+
+/// The kinds of file which can be imported, as told by their extension
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImportKind {
+    /// A bare program, in the given mode if the extension says which
+    Program(Option<MatrixMode>),
+    Project,
+}
+
+impl ImportKind {
+    pub fn from_extension(ext: &str) -> Self {
+        match ext {
+            PROJECT_EXTENSION => ImportKind::Project,
+            ext => ImportKind::Program(MatrixMode::from_extension(ext)),
+        }
+    }
+}
+
+impl ExportKind {
+    pub fn filter_name(self) -> &'static str {
+        match self {
+            ExportKind::Program(MatrixMode::L3) => "L3",
+            ExportKind::Program(MatrixMode::L3X) => "L3X",
+            ExportKind::Project => "L3X Project",
+            ExportKind::Trace(TraceFormat::JsonLines) => "JSON Lines",
+            ExportKind::Trace(TraceFormat::Csv) | ExportKind::BatchResults => "CSV",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportKind::Program(MatrixMode::L3) => "l3",
+            ExportKind::Program(MatrixMode::L3X) => "l3x",
+            ExportKind::Project => PROJECT_EXTENSION,
+            ExportKind::Trace(format) => format.extension(),
+            ExportKind::BatchResults => "csv",
+        }
+    }
+}
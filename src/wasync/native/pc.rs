@@ -3,9 +3,9 @@ use std::{ffi::OsStr, fs::OpenOptions, io::Write};
 use async_executor::Task;
 use rfd::FileHandle;
 
-use crate::matrix::MatrixMode;
+use crate::wasync::{ExportKind, ImportKind, PROJECT_EXTENSION};
 
-type ReadFileOutput = (Vec<u8>, Option<MatrixMode>);
+type ReadFileOutput = (Vec<u8>, ImportKind);
 
 #[derive(Default)]
 pub struct AsyncContext<'a> {
@@ -15,13 +15,10 @@ pub struct AsyncContext<'a> {
     pending_data: Option<Vec<u8>>,
 }
 
-fn l3x_extension(ext: &OsStr) -> Option<MatrixMode> {
-    if ext == "l3" {
-        Some(MatrixMode::L3)
-    } else if ext == "l3x" {
-        Some(MatrixMode::L3X)
-    } else {
-        None
+fn import_kind(ext: Option<&OsStr>) -> ImportKind {
+    match ext.and_then(OsStr::to_str) {
+        Some(ext) => ImportKind::from_extension(ext),
+        None => ImportKind::Program(None),
     }
 }
 
@@ -35,25 +32,20 @@ impl<'a> AsyncContext<'a> {
             self.read_file = Some(self.executor.spawn(async {
                 let file = rfd::AsyncFileDialog::new()
                     .add_filter("L3X File", &["l3x", "l3"])
+                    .add_filter("L3X Project", &[PROJECT_EXTENSION])
                     .add_filter("CSV", &["csv"])
                     .pick_file()
                     .await;
                 match file {
-                    Some(fi) => Some((
-                        fi.read().await,
-                        fi.path().extension().and_then(l3x_extension),
-                    )),
+                    Some(fi) => Some((fi.read().await, import_kind(fi.path().extension()))),
                     None => None,
                 }
             }));
         }
     }
 
-    pub fn start_file_export(&mut self, data: Vec<u8>, mode: MatrixMode) {
-        let (filter_name, extension) = match mode {
-            MatrixMode::L3 => ("L3", "l3"),
-            MatrixMode::L3X => ("L3X", "l3x"),
-        };
+    pub fn start_file_export(&mut self, data: Vec<u8>, kind: ExportKind) {
+        let (filter_name, extension) = (kind.filter_name(), kind.extension());
         if self.write_file.is_none() {
             self.write_file = Some(self.executor.spawn(async move {
                 let path = rfd::AsyncFileDialog::new()
@@ -66,7 +58,7 @@ impl<'a> AsyncContext<'a> {
         }
     }
 
-    pub fn try_open_file(&mut self) -> Option<(Vec<u8>, Option<MatrixMode>)> {
+    pub fn try_open_file(&mut self) -> Option<ReadFileOutput> {
         if_chain::if_chain! {
             if let Some(ref task) = self.read_file;
             if task.is_finished();
@@ -1,6 +1,9 @@
 use std::marker::PhantomData;
 
-use crate::matrix::MatrixMode;
+use crate::{
+    matrix::MatrixMode,
+    wasync::{ExportKind, ImportKind},
+};
 
 extern "C" {
     fn wasm_give_user_file(
@@ -29,11 +32,23 @@ unsafe fn give_user_file(filename: &str, data: &[u8]) {
     )
 }
 
-fn file_import_type() -> Option<MatrixMode> {
+/// Web builds cannot ask the user for a file name, so exports are given a fixed one
+fn export_file_name(kind: ExportKind) -> String {
+    let stem = match kind {
+        ExportKind::Program(_) => "l3x-ide_export",
+        ExportKind::Project => "l3x-ide_project",
+        ExportKind::Trace(_) => "l3x-ide_trace",
+        ExportKind::BatchResults => "l3x-ide_batch",
+    };
+    format!("{stem}.{}", kind.extension())
+}
+
+fn file_import_type() -> ImportKind {
     match unsafe { wasm_file_import_type() } {
-        1 => Some(MatrixMode::L3),
-        2 => Some(MatrixMode::L3X),
-        _ => None,
+        1 => ImportKind::Program(Some(MatrixMode::L3)),
+        2 => ImportKind::Program(Some(MatrixMode::L3X)),
+        3 => ImportKind::Project,
+        _ => ImportKind::Program(None),
     }
 }
 
@@ -47,7 +62,7 @@ impl<'a> AsyncContext<'a> {
         true
     }
 
-    pub fn try_open_file(&mut self) -> Option<(Vec<u8>, Option<MatrixMode>)> {
+    pub fn try_open_file(&mut self) -> Option<(Vec<u8>, ImportKind)> {
         let length = unsafe { wasm_file_import_len() };
         if length > 0 {
             let mut buf = Vec::with_capacity(length);
@@ -68,11 +83,7 @@ impl<'a> AsyncContext<'a> {
         unsafe { wasm_request_file_import() };
     }
 
-    pub fn start_file_export(&mut self, data: Vec<u8>, mode: MatrixMode) {
-        let name = match mode {
-            MatrixMode::L3 => "l3x-ide_export.l3",
-            MatrixMode::L3X => "l3x-ide_export.l3x",
-        };
-        unsafe { give_user_file(name, &data) }
+    pub fn start_file_export(&mut self, data: Vec<u8>, kind: ExportKind) {
+        unsafe { give_user_file(&export_file_name(kind), &data) }
     }
 }
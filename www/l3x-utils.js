@@ -34,13 +34,15 @@ const file_input = document.getElementById("file_input")
 var stored_file = null
 var stored_file_extension_type = null
 
-/// returns 1 if l3, 2 if l3x, any other code means unknown
+/// returns 1 if l3, 2 if l3x, 3 if a project, any other code means unknown
 const l3x_extension = function(extension) {
     if (!!extension) {
         if (extension === 'l3') {
             return 1;
         } else if (extension === 'l3x') {
             return 2;
+        } else if (extension === 'l3xproj') {
+            return 3;
         }
     }
 